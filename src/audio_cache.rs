@@ -0,0 +1,479 @@
+use crate::storage::{now, BackgroundFile};
+use crate::util::video_id;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+pub const AUDIO_CACHE_DIR: &str = "audio";
+const AUDIO_CACHE_INDEX: &str = "index.json";
+
+/// Bitrate of the cached Opus files, enough that filters don't bring out
+/// artifacts.
+const CACHE_BITRATE: &str = "128k";
+
+/// How much of a download is passed on to playback and the cache at once.
+const TEE_CHUNK: usize = 64 * 1024;
+
+/// A cached track.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CacheEntry {
+    pub file: String,
+    pub bytes: u64,
+    /// Seconds since the epoch of the last time the track was played.
+    pub last_used: u64,
+    /// How often it was played from the cache.
+    pub hits: u64,
+}
+
+/// The cache key of `uri`: the video id for Youtube, the url for the rest.
+pub fn cache_key(uri: &str) -> String {
+    match video_id(uri) {
+        Some(id) => id.to_string(),
+        None => uri.to_string(),
+    }
+}
+
+/// A file name for `key`, which is used as is when it is a plain video id.
+fn file_name(key: &str) -> String {
+    let plain = key.len() <= 64
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if plain {
+        return format!("{}.ogg", key);
+    }
+
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    format!("{:016x}.ogg", hasher.finish())
+}
+
+/// Passes the download of `youtube_dl` on to `playback` while transcoding
+/// it to Opus at `path`, returning the size of the file. The download carries
+/// on for the cache when playback stops early.
+fn tee(mut youtube_dl: Child, playback: ChildStdin, path: &Path) -> Result<u64, String> {
+    let mut download = youtube_dl
+        .stdout
+        .take()
+        .ok_or_else(|| String::from("youtube-dl has no output"))?;
+
+    // Playback goes on without the cache if the encoder doesn't start.
+    let temp = path.with_extension("ogg.part");
+    let encoder = Command::new("ffmpeg")
+        .args(["-hide_banner", "-nostats", "-loglevel", "error", "-y"])
+        .args(["-i", "-", "-vn", "-c:a", "libopus", "-b:a", CACHE_BITRATE])
+        .args(["-f", "ogg"])
+        .arg(&temp)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    let mut encoder = match encoder {
+        Ok(encoder) => Some(encoder),
+        Err(why) => {
+            warn!("Could not start ffmpeg to cache a track: {:?}", why);
+            None
+        }
+    };
+
+    let mut playing = Some(playback);
+    let mut encoding = encoder.as_mut().and_then(|encoder| encoder.stdin.take());
+    let mut chunk = vec![0; TEE_CHUNK];
+    let finished = loop {
+        let read = match download.read(&mut chunk) {
+            Ok(0) => break true,
+            Ok(read) => read,
+            Err(why) if why.kind() == ErrorKind::Interrupted => continue,
+            Err(_) => break false,
+        };
+        if let Some(stdin) = &mut playing {
+            if stdin.write_all(&chunk[..read]).is_err() {
+                playing = None;
+            }
+        }
+        if let Some(stdin) = &mut encoding {
+            if stdin.write_all(&chunk[..read]).is_err() {
+                encoding = None;
+            }
+        }
+        if playing.is_none() && encoding.is_none() {
+            break false;
+        }
+    };
+
+    // Closing the pipes lets both ffmpegs finish.
+    let encoded_all = finished && encoding.is_some();
+    drop(playing);
+    drop(encoding);
+    if !finished {
+        let _ = youtube_dl.kill();
+    }
+    let downloaded = youtube_dl.wait().map(|status| status.success());
+    let encoded = encoder.map(|mut encoder| encoder.wait().map(|status| status.success()));
+
+    if !encoded_all || !matches!(downloaded, Ok(true)) || !matches!(encoded, Some(Ok(true))) {
+        let _ = std::fs::remove_file(&temp);
+        return Err(String::from("the download did not finish"));
+    }
+    std::fs::rename(&temp, path).map_err(|why| why.to_string())?;
+
+    std::fs::metadata(path)
+        .map(|metadata| metadata.len())
+        .map_err(|why| why.to_string())
+}
+
+/// Deletes cached files that were dropped from the index.
+fn delete_files(paths: Vec<PathBuf>) {
+    for path in paths {
+        if let Err(why) = std::fs::remove_file(&path) {
+            warn!("Failed to delete cached {}: {:?}", path.display(), why);
+        }
+    }
+}
+
+/// Transcoded tracks kept on disk under the data directory, up to a total
+/// size, dropping the least recently played ones first.
+pub struct AudioCache {
+    dir: PathBuf,
+    index: BackgroundFile,
+    max_bytes: u64,
+    entries: HashMap<String, CacheEntry>,
+    /// Tracks being downloaded right now.
+    pending: HashSet<String>,
+}
+
+impl AudioCache {
+    /// Loads the index of the cache, forgetting entries whose files are gone.
+    /// A `max_bytes` of 0 turns the cache off.
+    pub fn load(data_dir: &Path, max_bytes: u64) -> std::io::Result<Self> {
+        let dir = data_dir.join(AUDIO_CACHE_DIR);
+        let index = dir.join(AUDIO_CACHE_INDEX);
+        let mut entries: HashMap<String, CacheEntry> = if index.exists() {
+            let text = std::fs::read_to_string(&index)?;
+            serde_json::from_str(&text)
+                .map_err(|why| std::io::Error::new(std::io::ErrorKind::InvalidData, why))?
+        } else {
+            HashMap::new()
+        };
+        entries.retain(|_, entry| dir.join(&entry.file).exists());
+
+        let mut cache = AudioCache {
+            index: BackgroundFile::new(index),
+            dir,
+            max_bytes,
+            entries,
+            pending: HashSet::new(),
+        };
+        delete_files(cache.evict());
+        Ok(cache)
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.max_bytes > 0
+    }
+
+    pub fn max_bytes(&self) -> u64 {
+        self.max_bytes
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.entries.values().map(|entry| entry.bytes).sum()
+    }
+
+    /// Cached tracks by key, most recently played first.
+    pub fn entries(&self) -> Vec<(&str, &CacheEntry)> {
+        let mut entries: Vec<(&str, &CacheEntry)> = self
+            .entries
+            .iter()
+            .map(|(key, entry)| (key.as_str(), entry))
+            .collect();
+        entries.sort_by(|a, b| b.1.last_used.cmp(&a.1.last_used));
+        entries
+    }
+
+    /// Counts a play of `uri` if it is cached.
+    fn hit(&mut self, uri: &str) {
+        if let Some(entry) = self.entries.get_mut(&cache_key(uri)) {
+            entry.last_used = now();
+            entry.hits += 1;
+            self.save();
+        }
+    }
+
+    fn insert(&mut self, key: String, file: String, bytes: u64) {
+        self.entries.insert(
+            key,
+            CacheEntry {
+                file,
+                bytes,
+                last_used: now(),
+                hits: 0,
+            },
+        );
+        let evicted = self.evict();
+        tokio::task::spawn_blocking(move || delete_files(evicted));
+        self.save();
+    }
+
+    /// Drops the least recently played tracks until the cache fits, returning
+    /// the files to delete.
+    fn evict(&mut self) -> Vec<PathBuf> {
+        let mut total = self.total_bytes();
+        let mut evicted = Vec::new();
+
+        while total > self.max_bytes {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.to_owned());
+            let entry = match oldest.and_then(|key| self.entries.remove(&key)) {
+                Some(entry) => entry,
+                None => break,
+            };
+
+            evicted.push(self.dir.join(&entry.file));
+            total -= entry.bytes;
+        }
+
+        evicted
+    }
+
+    /// Deletes every cached track, returning how many there were and their
+    /// total size.
+    pub fn purge(&mut self) -> (usize, u64) {
+        let purged = (self.entries.len(), self.total_bytes());
+
+        let files: Vec<PathBuf> = self
+            .entries
+            .drain()
+            .map(|(_, entry)| self.dir.join(entry.file))
+            .collect();
+        tokio::task::spawn_blocking(move || delete_files(files));
+        self.save();
+
+        purged
+    }
+
+    fn save(&mut self) {
+        match serde_json::to_string(&self.entries) {
+            Ok(text) => self.index.write(text),
+            Err(why) => warn!("Failed to save the audio cache index: {:?}", why),
+        }
+    }
+}
+
+/// Where a track plays from.
+pub enum CachedAudio {
+    /// Its file in the cache.
+    File(PathBuf),
+    /// A download that is cached as it plays.
+    Fill(Filling),
+    /// A download, as caching is off or the track is being cached already.
+    Download,
+}
+
+/// Where `uri` plays from. Restarts look it up again, so a track that was
+/// cached while it played seeks within the file afterwards.
+pub async fn lookup(cache: &Arc<RwLock<AudioCache>>, uri: &str) -> CachedAudio {
+    let key = cache_key(uri);
+    let mut audio_cache = cache.write().await;
+    if !audio_cache.enabled() {
+        return CachedAudio::Download;
+    }
+    if let Some(entry) = audio_cache.entries.get(&key) {
+        return CachedAudio::File(audio_cache.dir.join(&entry.file));
+    }
+    if !audio_cache.pending.insert(key.clone()) {
+        return CachedAudio::Download;
+    }
+
+    CachedAudio::Fill(Filling {
+        path: audio_cache.dir.join(file_name(&key)),
+        cache: cache.clone(),
+        key,
+    })
+}
+
+/// Counts a play of `uri` when it is in the cache. Called once when a track is
+/// queued, rather than on every restart.
+pub async fn count_play(cache: &Arc<RwLock<AudioCache>>, uri: &str) {
+    cache.write().await.hit(uri);
+}
+
+/// A track claimed for caching. Dropping it gives the claim up.
+pub struct Filling {
+    cache: Arc<RwLock<AudioCache>>,
+    key: String,
+    path: PathBuf,
+}
+
+impl Filling {
+    /// Plays the download of `youtube_dl` into `playback`, writing it to the
+    /// cache along the way.
+    pub fn start(self, youtube_dl: Child, playback: ChildStdin) {
+        tokio::spawn(async move {
+            let result = {
+                let path = self.path.clone();
+                tokio::task::spawn_blocking(move || {
+                    std::fs::create_dir_all(path.parent().unwrap_or(&path))
+                        .map_err(|why| why.to_string())?;
+                    tee(youtube_dl, playback, &path)
+                })
+                .await
+            };
+
+            let mut cache = self.cache.write().await;
+            match result {
+                Ok(Ok(bytes)) if bytes <= cache.max_bytes => {
+                    let file = file_name(&self.key);
+                    cache.insert(self.key.clone(), file, bytes);
+                }
+                Ok(Ok(_)) => {
+                    let path = self.path.clone();
+                    tokio::task::spawn_blocking(move || delete_files(vec![path]));
+                }
+                Ok(Err(why)) => warn!("Failed to cache {}: {}", self.key, why),
+                Err(why) => warn!("Caching {} panicked: {:?}", self.key, why),
+            }
+        });
+    }
+}
+
+impl Drop for Filling {
+    fn drop(&mut self) {
+        let cache = self.cache.clone();
+        let key = std::mem::take(&mut self.key);
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            handle.spawn(async move {
+                cache.write().await.pending.remove(&key);
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(file: &str, bytes: u64, last_used: u64) -> CacheEntry {
+        CacheEntry {
+            file: file.to_string(),
+            bytes,
+            last_used,
+            hits: 0,
+        }
+    }
+
+    #[test]
+    fn keys_tracks() {
+        let cases = [
+            ("https://www.youtube.com/watch?v=dQw4w9WgXcQ", "dQw4w9WgXcQ"),
+            ("https://youtu.be/dQw4w9WgXcQ?t=10", "dQw4w9WgXcQ"),
+            (
+                "https://soundcloud.com/artist/track",
+                "https://soundcloud.com/artist/track",
+            ),
+        ];
+
+        for (uri, expected) in cases {
+            assert_eq!(cache_key(uri), expected, "{}", uri);
+        }
+    }
+
+    #[test]
+    fn names_files() {
+        assert_eq!(file_name("dQw4w9WgXcQ"), "dQw4w9WgXcQ.ogg");
+
+        let url = "https://soundcloud.com/artist/track";
+        let long = "a".repeat(65);
+        for key in [url, "../../etc/passwd", long.as_str()] {
+            let name = file_name(key);
+            assert_eq!(name.len(), 20, "{}", key);
+            assert!(name.ends_with(".ogg"), "{}", key);
+            assert!(name[..16].chars().all(|c| c.is_ascii_hexdigit()), "{}", key);
+            assert_eq!(file_name(key), name, "{}", key);
+        }
+        assert_ne!(
+            file_name(url),
+            file_name("https://soundcloud.com/artist/other")
+        );
+    }
+
+    #[test]
+    fn evicts_least_recently_played() {
+        // The cache size, and which of three 40 byte tracks played at times
+        // 1, 3 and 2 are left.
+        let cases = [
+            (120, vec!["a", "b", "c"]),
+            (100, vec!["b", "c"]),
+            (79, vec!["b"]),
+            (0, vec![]),
+        ];
+
+        for (max_bytes, expected) in cases {
+            let dir = PathBuf::from("audio");
+            let mut cache = AudioCache {
+                index: BackgroundFile::new(dir.join(AUDIO_CACHE_INDEX)),
+                dir: dir.clone(),
+                max_bytes,
+                entries: HashMap::from([
+                    (String::from("a"), entry("a.ogg", 40, 1)),
+                    (String::from("b"), entry("b.ogg", 40, 3)),
+                    (String::from("c"), entry("c.ogg", 40, 2)),
+                ]),
+                pending: HashSet::new(),
+            };
+
+            let evicted = cache.evict();
+            let mut left: Vec<&str> = cache.entries.keys().map(String::as_str).collect();
+            left.sort_unstable();
+            assert_eq!(left, expected, "{}", max_bytes);
+            assert_eq!(evicted.len(), 3 - expected.len(), "{}", max_bytes);
+            for path in evicted {
+                assert!(path.starts_with(&dir));
+                let key = path.file_stem().and_then(|stem| stem.to_str()).unwrap();
+                assert!(!expected.contains(&key), "{}", max_bytes);
+            }
+        }
+    }
+
+    #[test]
+    fn deletes_evicted_files_when_loading() {
+        let data_dir =
+            std::env::temp_dir().join(format!("caitlyn-audio-cache-test-{}", std::process::id()));
+        let dir = data_dir.join(AUDIO_CACHE_DIR);
+        std::fs::create_dir_all(&dir).unwrap();
+        for file in ["old.ogg", "new.ogg"] {
+            std::fs::write(dir.join(file), [0u8; 40]).unwrap();
+        }
+        let entries = HashMap::from([
+            (String::from("old"), entry("old.ogg", 40, 1)),
+            (String::from("new"), entry("new.ogg", 40, 2)),
+            (String::from("gone"), entry("gone.ogg", 40, 3)),
+        ]);
+        std::fs::write(
+            dir.join(AUDIO_CACHE_INDEX),
+            serde_json::to_string(&entries).unwrap(),
+        )
+        .unwrap();
+
+        let cache = AudioCache::load(&data_dir, 50).unwrap();
+        let keys: Vec<&str> = cache.entries().iter().map(|(key, _)| *key).collect();
+        let old_exists = dir.join("old.ogg").exists();
+        let new_exists = dir.join("new.ogg").exists();
+        std::fs::remove_dir_all(&data_dir).unwrap();
+
+        // Entries whose files are gone are forgotten, and the oldest of the
+        // rest is deleted to fit.
+        assert_eq!(keys, ["new"]);
+        assert!(!old_exists);
+        assert!(new_exists);
+    }
+}
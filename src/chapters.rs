@@ -0,0 +1,230 @@
+use serenity::prelude::TypeMapKey;
+use std::time::Duration;
+
+/// A chapter of a video, starting at `start` in the time of the video.
+#[derive(Debug, Clone)]
+pub struct Chapter {
+    pub start: Duration,
+    pub title: String,
+}
+
+impl Chapter {
+    /// The title of the chapter at `index`, or its number if it has none.
+    pub fn name(&self, index: usize) -> String {
+        if self.title.is_empty() {
+            format!("Chapter {}", index + 1)
+        } else {
+            self.title.to_owned()
+        }
+    }
+}
+
+/// The chapters of a track, kept in the typemap of its handle.
+pub struct Chapters;
+
+impl TypeMapKey for Chapters {
+    type Value = Vec<Chapter>;
+}
+
+/// The chapters in youtube-dl's JSON output for a video, or failing that the
+/// timestamps listed in its description.
+pub fn from_ytdl(value: &serde_json::Value) -> Vec<Chapter> {
+    let listed: Vec<Chapter> = value
+        .get("chapters")
+        .and_then(|chapters| chapters.as_array())
+        .map(|chapters| {
+            chapters
+                .iter()
+                .filter_map(|chapter| {
+                    Some(Chapter {
+                        start: Duration::from_secs_f64(
+                            chapter.get("start_time")?.as_f64()?.max(0.0),
+                        ),
+                        title: chapter.get("title")?.as_str()?.trim().to_string(),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    if !listed.is_empty() {
+        return listed;
+    }
+
+    value
+        .get("description")
+        .and_then(|description| description.as_str())
+        .map(from_description)
+        .unwrap_or_default()
+}
+
+/// Parses `1:02:03`, `2:03` or `0:03` into a duration.
+fn parse_timestamp(timestamp: &str) -> Option<Duration> {
+    let parts: Vec<u64> = timestamp
+        .split(':')
+        .map(|part| part.parse::<u64>().ok())
+        .collect::<Option<_>>()?;
+
+    let seconds = match parts.as_slice() {
+        [minutes, seconds] if *seconds < 60 => minutes * 60 + seconds,
+        [hours, minutes, seconds] if *minutes < 60 && *seconds < 60 => {
+            hours * 3600 + minutes * 60 + seconds
+        }
+        _ => return None,
+    };
+    Some(Duration::from_secs(seconds))
+}
+
+/// Chapters from lines like `12:34 Title` or `Title - 12:34` in a video
+/// description. Like Youtube, it only counts them as chapters when the first
+/// starts at 0:00 and they are in order.
+pub fn from_description(description: &str) -> Vec<Chapter> {
+    let mut chapters: Vec<Chapter> = Vec::new();
+
+    for line in description.lines() {
+        let found = line.split_whitespace().find_map(|word| {
+            let word = word.trim_matches(|c: char| !c.is_ascii_digit());
+            parse_timestamp(word).map(|start| (word, start))
+        });
+        let (timestamp, start) = match found {
+            Some(found) => found,
+            None => continue,
+        };
+
+        let title = line
+            .replacen(timestamp, "", 1)
+            .trim_matches(|c: char| c.is_whitespace() || "-–—:|()[]".contains(c))
+            .to_string();
+        chapters.push(Chapter { start, title });
+    }
+
+    let in_order = chapters
+        .windows(2)
+        .all(|pair| pair[0].start < pair[1].start);
+    match chapters.first() {
+        Some(first) if first.start.is_zero() && chapters.len() >= 2 && in_order => chapters,
+        _ => Vec::new(),
+    }
+}
+
+/// The index of the chapter playing at `position`.
+pub fn current_chapter(chapters: &[Chapter], position: Duration) -> Option<usize> {
+    chapters
+        .iter()
+        .rposition(|chapter| chapter.start <= position)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chapters(description: &str) -> Vec<(u64, String)> {
+        from_description(description)
+            .into_iter()
+            .map(|chapter| (chapter.start.as_secs(), chapter.title))
+            .collect()
+    }
+
+    #[test]
+    fn parses_timestamps() {
+        let cases = [
+            ("0:00", Some(0)),
+            ("2:03", Some(123)),
+            ("12:34", Some(754)),
+            ("1:02:03", Some(3723)),
+            ("90:00", Some(5400)),
+            ("1:60", None),
+            ("1:60:00", None),
+            ("1:00:60", None),
+            ("123", None),
+            ("1:2:3:4", None),
+            ("a:bc", None),
+            ("", None),
+        ];
+
+        for (timestamp, expected) in cases {
+            assert_eq!(
+                parse_timestamp(timestamp).map(|start| start.as_secs()),
+                expected,
+                "{}",
+                timestamp
+            );
+        }
+    }
+
+    #[test]
+    fn finds_chapters_in_descriptions() {
+        let cases: [(&str, &[(u64, &str)]); 9] = [
+            (
+                "Tracklist:\n0:00 Intro\n1:30 Verse\n3:05 Outro",
+                &[(0, "Intro"), (90, "Verse"), (185, "Outro")],
+            ),
+            (
+                "Intro - 0:00\nThe Long One - 4:59\nFinale | 1:02:03",
+                &[(0, "Intro"), (299, "The Long One"), (3723, "Finale")],
+            ),
+            (
+                "[0:00] Start\n(12:00) Middle\n1:00:00 — End",
+                &[(0, "Start"), (720, "Middle"), (3600, "End")],
+            ),
+            ("00:00 Untitled\n02:00", &[(0, "Untitled"), (120, "")]),
+            // The first chapter has to start at the beginning.
+            ("0:10 Intro\n1:30 Verse", &[]),
+            // And they have to be in order.
+            ("0:00 Intro\n3:05 Outro\n1:30 Verse", &[]),
+            ("0:00 Intro\n0:00 Again", &[]),
+            // A single timestamp isn't a chapter list.
+            ("0:00 Intro", &[]),
+            ("No timestamps here, just 100% music.", &[]),
+        ];
+
+        for (description, expected) in cases {
+            let expected: Vec<(u64, String)> = expected
+                .iter()
+                .map(|(start, title)| (*start, title.to_string()))
+                .collect();
+            assert_eq!(chapters(description), expected, "{}", description);
+        }
+    }
+
+    #[test]
+    fn finds_the_current_chapter() {
+        let chapters = from_description("0:00 Intro\n1:30 Verse\n3:05 Outro");
+        let cases = [
+            (0, Some(0)),
+            (89, Some(0)),
+            (90, Some(1)),
+            (184, Some(1)),
+            (185, Some(2)),
+            (10_000, Some(2)),
+        ];
+
+        for (position, expected) in cases {
+            assert_eq!(
+                current_chapter(&chapters, Duration::from_secs(position)),
+                expected,
+                "{}",
+                position
+            );
+        }
+        assert_eq!(current_chapter(&[], Duration::ZERO), None);
+    }
+
+    #[test]
+    fn prefers_listed_chapters() {
+        let listed = serde_json::json!({
+            "chapters": [
+                {"start_time": 0.0, "title": " Intro "},
+                {"start_time": 42.5, "title": "Drop"}
+            ],
+            "description": "0:00 Ignored\n1:00 Also ignored"
+        });
+        let titles: Vec<String> = from_ytdl(&listed)
+            .into_iter()
+            .map(|chapter| chapter.title)
+            .collect();
+        assert_eq!(titles, ["Intro", "Drop"]);
+
+        let described = serde_json::json!({"description": "0:00 One\n1:00 Two"});
+        assert_eq!(from_ytdl(&described).len(), 2);
+    }
+}
@@ -0,0 +1,180 @@
+use crate::chapters::{current_chapter, Chapter, Chapters};
+use crate::commands::controls::{check_dj, current_track};
+use crate::commands::invocation::Invocation;
+use crate::error::{CaitlynResult, Error};
+use crate::filters::guild_filters;
+use crate::pagination::{truncate, EMBED_DESCRIPTION_LIMIT};
+use crate::storage::guild_settings;
+use crate::util::format_duration;
+use serenity::builder::CreateEmbed;
+use serenity::framework::standard::macros::command;
+use serenity::framework::standard::{Args, CommandResult};
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+use songbird::tracks::TrackHandle;
+use std::time::Duration;
+
+/// Which chapter `chapter` seeks to.
+pub enum ChapterTarget {
+    Next,
+    Previous,
+    /// A chapter number, counted from 1.
+    Number(usize),
+}
+
+impl ChapterTarget {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "next" | "n" => Some(ChapterTarget::Next),
+            "previous" | "prev" | "p" | "back" => Some(ChapterTarget::Previous),
+            number => number.parse::<usize>().ok().map(ChapterTarget::Number),
+        }
+    }
+}
+
+/// The chapters of the current track, where it is in them, and how fast it
+/// plays, to convert between played and video time.
+async fn track_chapters(
+    ctx: &Context,
+    guild_id: GuildId,
+) -> CaitlynResult<(TrackHandle, Vec<Chapter>, Option<usize>, f64)> {
+    let track = current_track(ctx, guild_id).await?;
+    let chapters = track
+        .typemap()
+        .read()
+        .await
+        .get::<Chapters>()
+        .cloned()
+        .unwrap_or_default();
+    if chapters.is_empty() {
+        return Err(Error::BadArgument(String::from(
+            "The current track has no chapters.",
+        )));
+    }
+
+    let speed = guild_filters(&ctx.data, guild_id)
+        .await
+        .read()
+        .await
+        .speed_factor();
+    let position = match track.get_info().await {
+        Ok(info) => info.position.mul_f64(speed),
+        Err(_) => Duration::ZERO,
+    };
+    let current = current_chapter(&chapters, position);
+
+    Ok((track, chapters, current, speed))
+}
+
+#[command]
+#[only_in(guilds)]
+#[description = "List the chapters of the current track."]
+#[bucket = "embeds"]
+async fn chapters(ctx: &Context, msg: &Message) -> CommandResult {
+    Ok(_chapters(ctx, &Invocation::Message(msg)).await?)
+}
+
+pub async fn _chapters(ctx: &Context, invocation: &Invocation<'_>) -> CaitlynResult {
+    let guild_id = match invocation.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    let (config, settings) = guild_settings(ctx, Some(guild_id)).await;
+    let (_, chapters, current, _) = track_chapters(ctx, guild_id).await?;
+
+    let lines: Vec<String> = chapters
+        .iter()
+        .enumerate()
+        .map(|(i, chapter)| {
+            let line = format!(
+                "`{}.` {} ({})",
+                i + 1,
+                truncate(&chapter.name(i), 80),
+                format_duration(chapter.start)
+            );
+            if current == Some(i) {
+                format!("**{}**", line)
+            } else {
+                line
+            }
+        })
+        .collect();
+
+    let mut embed = CreateEmbed::default();
+    embed
+        .colour(settings.colour(&config))
+        .title(format!("Chapters ({})", chapters.len()))
+        .description(truncate(&lines.join("\n"), EMBED_DESCRIPTION_LIMIT))
+        .footer(|f| f.text("Jump to one with chapter <number>, next or prev."));
+    invocation.send_embed(ctx, embed).await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[aliases(ch)]
+#[min_args(1)]
+#[max_args(1)]
+#[description = "Jump to the next or previous chapter of the current track, or to a numbered one."]
+#[usage = "<next|prev|number>"]
+#[example = "next"]
+#[bucket = "control"]
+async fn chapter(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let target = ChapterTarget::parse(args.message()).ok_or_else(|| {
+        Error::BadArgument(format!(
+            "`{}` is not a chapter, use next, prev or a chapter number.",
+            args.message()
+        ))
+    })?;
+
+    Ok(_chapter(ctx, &Invocation::Message(msg), target).await?)
+}
+
+pub async fn _chapter(
+    ctx: &Context,
+    invocation: &Invocation<'_>,
+    target: ChapterTarget,
+) -> CaitlynResult {
+    let guild_id = match invocation.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    check_dj(ctx, invocation).await?;
+    let (track, chapters, current, speed) = track_chapters(ctx, guild_id).await?;
+
+    let index = match target {
+        ChapterTarget::Next => current.map_or(0, |current| current + 1),
+        ChapterTarget::Previous => current.unwrap_or(0).saturating_sub(1),
+        ChapterTarget::Number(number) => number.wrapping_sub(1),
+    };
+    let chapter = match chapters.get(index) {
+        Some(chapter) => chapter,
+        None if matches!(target, ChapterTarget::Next) => {
+            return Err(Error::BadArgument(String::from(
+                "This is the last chapter.",
+            )))
+        }
+        None => {
+            return Err(Error::BadArgument(format!(
+                "Pick a chapter from 1 to {}.",
+                chapters.len()
+            )))
+        }
+    };
+
+    track
+        .seek_time(chapter.start.div_f64(speed))
+        .map_err(|_| Error::Source(String::from("This track can't be seeked.")))?;
+
+    invocation
+        .say(
+            ctx,
+            format!("Jumped to chapter {}: {}.", index + 1, chapter.name(index)),
+        )
+        .await?;
+
+    Ok(())
+}
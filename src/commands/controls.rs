@@ -0,0 +1,405 @@
+use crate::commands::invocation::Invocation;
+use crate::error::{CaitlynResult, Error};
+use crate::storage::guild_settings;
+use crate::{Guilds, Queue};
+use rand::seq::SliceRandom;
+use serenity::builder::CreateComponents;
+use serenity::framework::standard::macros::command;
+use serenity::framework::standard::{Args, CommandResult};
+use serenity::model::application::component::ButtonStyle;
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+use songbird::tracks::{LoopState, PlayMode, TrackHandle};
+
+/// How much the volume buttons change the volume, in percent.
+pub const VOLUME_STEP: u8 = 10;
+pub const MAX_VOLUME: u8 = 200;
+
+/// What the playback controls show: whether the track is paused or looping,
+/// and the volume in percent.
+#[derive(Clone, Copy)]
+pub struct PlaybackState {
+    pub paused: bool,
+    pub looping: bool,
+    pub volume: u8,
+}
+
+/// Fails unless the author may control playback: anyone when the server has
+/// no DJ role, otherwise members with the role or Manage Server.
+pub async fn check_dj(ctx: &Context, invocation: &Invocation<'_>) -> CaitlynResult {
+    let (_, settings) = guild_settings(ctx, invocation.guild_id()).await;
+
+    let role = match settings.dj_role() {
+        Some(role) => role,
+        None => return Ok(()),
+    };
+    if invocation.has_permissions(ctx, Permissions::MANAGE_GUILD) || invocation.has_role(ctx, role)
+    {
+        return Ok(());
+    }
+    Err(Error::PermissionDenied(format!(
+        "You need the {} role to control playback.",
+        role.mention()
+    )))
+}
+
+pub async fn current_track(ctx: &Context, guild_id: GuildId) -> CaitlynResult<TrackHandle> {
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+
+    let handler_lock = manager.get(guild_id).ok_or(Error::NotConnected)?;
+    let current = handler_lock.lock().await.queue().current();
+
+    current.ok_or(Error::NothingPlaying)
+}
+
+pub async fn playback_state(track: &TrackHandle) -> Option<PlaybackState> {
+    let info = track.get_info().await.ok()?;
+
+    Some(PlaybackState {
+        paused: info.playing == PlayMode::Pause,
+        looping: info.loops == LoopState::Infinite,
+        volume: (info.volume * 100.0).round() as u8,
+    })
+}
+
+/// Pauses or resumes the current track. Pausing also pauses the next one if
+/// it is fading in, the current track starts it again once it is resumed.
+pub async fn set_paused(ctx: &Context, guild_id: GuildId, paused: bool) -> CaitlynResult {
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+    let handler_lock = manager.get(guild_id).ok_or(Error::NotConnected)?;
+    let queued = handler_lock.lock().await.queue().current_queue();
+
+    let track = queued.first().ok_or(Error::NothingPlaying)?;
+    if !paused {
+        return track.play().map_err(|_| Error::NothingPlaying);
+    }
+    for next in queued.iter().skip(1) {
+        let _ = next.pause();
+    }
+    track.pause().map_err(|_| Error::NothingPlaying)
+}
+
+/// Loops the current track forever, or stops looping it. Returns whether it
+/// loops now.
+pub async fn toggle_loop(ctx: &Context, guild_id: GuildId) -> CaitlynResult<bool> {
+    let track = current_track(ctx, guild_id).await?;
+    let looping = match playback_state(&track).await {
+        Some(state) => state.looping,
+        None => return Err(Error::NothingPlaying),
+    };
+
+    let result = if looping {
+        track.disable_loop()
+    } else {
+        track.enable_loop()
+    };
+    result.map_err(|_| Error::Source(String::from("This track can't be looped.")))?;
+
+    Ok(!looping)
+}
+
+/// Shuffles everything after the current track, keeping the songbird queue and
+/// our track list in the same order. Returns how many tracks were shuffled.
+pub async fn shuffle_queue(ctx: &Context, guild_id: GuildId) -> CaitlynResult<usize> {
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+    let handler_lock = manager.get(guild_id).ok_or(Error::NotConnected)?;
+
+    let queue_lock = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<Queue>()
+            .expect("Expected Queue in TypeMap.")
+            .clone()
+    };
+
+    let handler = handler_lock.lock().await;
+    let mut playlist = queue_lock.write().await;
+
+    let len = handler.queue().len().min(playlist.len());
+    if len < 3 {
+        return Ok(0);
+    }
+    let mut order: Vec<usize> = (0..len - 1).collect();
+    order.shuffle(&mut rand::thread_rng());
+
+    handler.queue().modify_queue(|queue| {
+        let upcoming: Vec<_> = queue.drain(1..len).collect();
+        for (i, item) in reorder(upcoming, &order).into_iter().enumerate() {
+            queue.insert(i + 1, item);
+        }
+    });
+    let upcoming: Vec<_> = playlist.drain(1..len).collect();
+    for (i, item) in reorder(upcoming, &order).into_iter().enumerate() {
+        playlist.insert(i + 1, item);
+    }
+
+    Ok(len - 1)
+}
+
+/// `items` rearranged so that the item at `order[i]` ends up at `i`.
+fn reorder<T>(items: Vec<T>, order: &[usize]) -> Vec<T> {
+    let mut items: Vec<Option<T>> = items.into_iter().map(Some).collect();
+
+    order
+        .iter()
+        .filter_map(|i| items.get_mut(*i).and_then(Option::take))
+        .collect()
+}
+
+/// Sets the volume of every queued track, and remembers it for the next ones.
+pub async fn set_volume(ctx: &Context, guild_id: GuildId, percent: u8) -> CaitlynResult {
+    let percent = percent.min(MAX_VOLUME);
+
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+    let handler_lock = manager.get(guild_id).ok_or(Error::NotConnected)?;
+    for track in handler_lock.lock().await.queue().current_queue() {
+        let _ = track.set_volume(f32::from(percent) / 100.0);
+    }
+
+    let store = {
+        let data_read = ctx.data.read().await;
+
+        data_read
+            .get::<Guilds>()
+            .expect("Expected guild settings in TypeMap.")
+            .clone()
+    };
+    store
+        .write()
+        .await
+        .update(guild_id, |settings| {
+            settings.volume = Some(percent);
+            Ok(())
+        })
+        .map_err(|why| Error::BadArgument(format!("Could not change the volume: {}.", why)))
+}
+
+pub fn control_buttons(
+    c: &mut CreateComponents,
+    state: PlaybackState,
+    disabled: bool,
+) -> &mut CreateComponents {
+    c.create_action_row(|r| {
+        r.create_button(|b| {
+            b.custom_id("np_pause")
+                .label(if state.paused { "Resume" } else { "Pause" })
+                .style(ButtonStyle::Primary)
+                .disabled(disabled)
+        })
+        .create_button(|b| {
+            b.custom_id("np_skip")
+                .label("Skip")
+                .style(ButtonStyle::Secondary)
+                .disabled(disabled)
+        })
+        .create_button(|b| {
+            b.custom_id("np_stop")
+                .label("Stop")
+                .style(ButtonStyle::Danger)
+                .disabled(disabled)
+        })
+    })
+    .create_action_row(|r| {
+        r.create_button(|b| {
+            b.custom_id("np_loop")
+                .label(if state.looping {
+                    "Loop: on"
+                } else {
+                    "Loop: off"
+                })
+                .style(if state.looping {
+                    ButtonStyle::Success
+                } else {
+                    ButtonStyle::Secondary
+                })
+                .disabled(disabled)
+        })
+        .create_button(|b| {
+            b.custom_id("np_shuffle")
+                .label("Shuffle")
+                .style(ButtonStyle::Secondary)
+                .disabled(disabled)
+        })
+        .create_button(|b| {
+            b.custom_id("np_voldown")
+                .label(format!("Vol -{}", VOLUME_STEP))
+                .style(ButtonStyle::Secondary)
+                .disabled(disabled || state.volume == 0)
+        })
+        .create_button(|b| {
+            b.custom_id("np_volup")
+                .label(format!("Vol +{}", VOLUME_STEP))
+                .style(ButtonStyle::Secondary)
+                .disabled(disabled || state.volume >= MAX_VOLUME)
+        })
+    })
+}
+
+#[command]
+#[only_in(guilds)]
+#[description = "Pause the current track."]
+#[bucket = "control"]
+async fn pause(ctx: &Context, msg: &Message) -> CommandResult {
+    Ok(_pause(ctx, &Invocation::Message(msg), true).await?)
+}
+
+#[command]
+#[only_in(guilds)]
+#[aliases(unpause)]
+#[description = "Resume the current track."]
+#[bucket = "control"]
+async fn resume(ctx: &Context, msg: &Message) -> CommandResult {
+    Ok(_pause(ctx, &Invocation::Message(msg), false).await?)
+}
+
+pub async fn _pause(ctx: &Context, invocation: &Invocation<'_>, paused: bool) -> CaitlynResult {
+    let guild_id = match invocation.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    check_dj(ctx, invocation).await?;
+    set_paused(ctx, guild_id, paused).await?;
+
+    invocation
+        .say(ctx, if paused { "Paused." } else { "Resumed." })
+        .await?;
+
+    Ok(())
+}
+
+#[command("loop")]
+#[only_in(guilds)]
+#[aliases(repeat)]
+#[description = "Loop the current track, or stop looping it."]
+#[bucket = "control"]
+async fn repeat(ctx: &Context, msg: &Message) -> CommandResult {
+    Ok(_loop(ctx, &Invocation::Message(msg)).await?)
+}
+
+pub async fn _loop(ctx: &Context, invocation: &Invocation<'_>) -> CaitlynResult {
+    let guild_id = match invocation.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    check_dj(ctx, invocation).await?;
+    let looping = toggle_loop(ctx, guild_id).await?;
+
+    invocation
+        .say(
+            ctx,
+            if looping {
+                "Looping the current track."
+            } else {
+                "Stopped looping."
+            },
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[description = "Shuffle the upcoming tracks."]
+#[bucket = "control"]
+async fn shuffle(ctx: &Context, msg: &Message) -> CommandResult {
+    Ok(_shuffle(ctx, &Invocation::Message(msg)).await?)
+}
+
+pub async fn _shuffle(ctx: &Context, invocation: &Invocation<'_>) -> CaitlynResult {
+    let guild_id = match invocation.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    check_dj(ctx, invocation).await?;
+    let shuffled = shuffle_queue(ctx, guild_id).await?;
+
+    invocation
+        .say(
+            ctx,
+            if shuffled == 0 {
+                String::from("There's nothing to shuffle.")
+            } else {
+                format!("Shuffled {} tracks.", shuffled)
+            },
+        )
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[aliases(vol)]
+#[max_args(1)]
+#[description = "Show the volume, or set it in percent."]
+#[usage = "[0-200]"]
+#[example = "50"]
+#[bucket = "control"]
+async fn volume(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let percent = if args.is_empty() {
+        None
+    } else {
+        Some(args.single::<u8>().map_err(|_| {
+            Error::BadArgument(format!(
+                "`{}` is not a volume, pick a number from 0 to {}.",
+                args.message(),
+                MAX_VOLUME
+            ))
+        })?)
+    };
+
+    Ok(_volume(ctx, &Invocation::Message(msg), percent).await?)
+}
+
+pub async fn _volume(
+    ctx: &Context,
+    invocation: &Invocation<'_>,
+    percent: Option<u8>,
+) -> CaitlynResult {
+    let guild_id = match invocation.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    let percent = match percent {
+        Some(percent) => percent,
+        None => {
+            let (_, settings) = guild_settings(ctx, Some(guild_id)).await;
+            invocation
+                .say(ctx, format!("The volume is {}%.", settings.volume()))
+                .await?;
+            return Ok(());
+        }
+    };
+    if percent > MAX_VOLUME {
+        return Err(Error::BadArgument(format!(
+            "Pick a volume from 0 to {}.",
+            MAX_VOLUME
+        )));
+    }
+
+    check_dj(ctx, invocation).await?;
+    set_volume(ctx, guild_id, percent).await?;
+
+    invocation
+        .say(ctx, format!("Volume set to {}%.", percent))
+        .await?;
+
+    Ok(())
+}
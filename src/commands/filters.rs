@@ -0,0 +1,273 @@
+use crate::commands::controls::{check_dj, current_track};
+use crate::commands::invocation::Invocation;
+use crate::error::{CaitlynResult, Error};
+use crate::filters::{
+    self, guild_filters, parse_equalizer, valid_factor, AudioFilters, FilterPreset, EQ_PRESETS,
+    FILTER_PRESETS, MAX_SPEED, MIN_SPEED,
+};
+use serenity::framework::standard::macros::command;
+use serenity::framework::standard::{Args, CommandResult};
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+
+/// Changes the filters of `guild_id` and restarts the current track where it
+/// is, so the change is heard right away. Returns the new filters.
+async fn apply_filters<F>(ctx: &Context, guild_id: GuildId, change: F) -> AudioFilters
+where
+    F: FnOnce(&mut AudioFilters),
+{
+    let shared = guild_filters(&ctx.data, guild_id).await;
+    let filters = {
+        let mut filters = shared.write().await;
+        change(&mut filters);
+        filters.clone()
+    };
+
+    if let Ok(track) = current_track(ctx, guild_id).await {
+        if let Ok(info) = track.get_info().await {
+            if let Err(why) = track.seek_time(info.position) {
+                warn!("Failed to restart the track with new filters: {:?}", why);
+            }
+        }
+    }
+
+    filters
+}
+
+async fn show_filters(
+    ctx: &Context,
+    invocation: &Invocation<'_>,
+    guild_id: GuildId,
+) -> CaitlynResult {
+    let filters = guild_filters(&ctx.data, guild_id)
+        .await
+        .read()
+        .await
+        .clone();
+
+    invocation
+        .say(ctx, format!("Filters: {}.", filters.describe()))
+        .await?;
+
+    Ok(())
+}
+
+/// Fails unless `factor` is a speed or pitch the filters can play.
+fn check_factor(factor: f64, what: &str) -> CaitlynResult<f64> {
+    if valid_factor(factor) {
+        return Ok(factor);
+    }
+    Err(Error::BadArgument(format!(
+        "Pick a {} from {} to {}.",
+        what, MIN_SPEED, MAX_SPEED
+    )))
+}
+
+fn parse_factor(value: &str, what: &str) -> CaitlynResult<f64> {
+    filters::parse_factor(value).ok_or_else(|| {
+        Error::BadArgument(format!(
+            "`{}` is not a {}, pick a number from {} to {}.",
+            value, what, MIN_SPEED, MAX_SPEED
+        ))
+    })
+}
+
+#[command]
+#[only_in(guilds)]
+#[aliases(filters, fx)]
+#[max_args(1)]
+#[description = "Show the filters, apply a preset, or turn all filters off."]
+#[usage = "[bassboost|nightcore|vaporwave|8d|karaoke|off]"]
+#[example = "nightcore"]
+#[bucket = "control"]
+async fn filter(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let name = if args.is_empty() {
+        None
+    } else {
+        Some(args.message().to_string())
+    };
+
+    Ok(_filter(ctx, &Invocation::Message(msg), name).await?)
+}
+
+pub async fn _filter(
+    ctx: &Context,
+    invocation: &Invocation<'_>,
+    name: Option<String>,
+) -> CaitlynResult {
+    let guild_id = match invocation.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    let name = match name {
+        Some(name) => name,
+        None => return show_filters(ctx, invocation, guild_id).await,
+    };
+
+    let reply = if name.eq_ignore_ascii_case("off") {
+        check_dj(ctx, invocation).await?;
+        apply_filters(ctx, guild_id, |filters| *filters = AudioFilters::default()).await;
+        String::from("Turned all filters off.")
+    } else {
+        let preset = FilterPreset::parse(&name).ok_or_else(|| {
+            Error::BadArgument(format!(
+                "`{}` is not a filter, pick one of {} or off.",
+                name,
+                FILTER_PRESETS.join(", ")
+            ))
+        })?;
+        check_dj(ctx, invocation).await?;
+        let filters = apply_filters(ctx, guild_id, |filters| filters.preset = Some(preset)).await;
+        format!("Filters: {}.", filters.describe())
+    };
+
+    invocation.say(ctx, reply).await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[aliases(tempo)]
+#[max_args(1)]
+#[description = "Show the playback speed, or change it without changing the pitch."]
+#[usage = "[0.5-2.0]"]
+#[example = "1.25"]
+#[bucket = "control"]
+async fn speed(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let speed = if args.is_empty() {
+        None
+    } else {
+        Some(parse_factor(args.message(), "speed")?)
+    };
+
+    Ok(_speed(ctx, &Invocation::Message(msg), speed).await?)
+}
+
+pub async fn _speed(
+    ctx: &Context,
+    invocation: &Invocation<'_>,
+    speed: Option<f64>,
+) -> CaitlynResult {
+    let guild_id = match invocation.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    let speed = match speed {
+        Some(speed) => check_factor(speed, "speed")?,
+        None => return show_filters(ctx, invocation, guild_id).await,
+    };
+
+    check_dj(ctx, invocation).await?;
+    apply_filters(ctx, guild_id, |filters| filters.speed = speed).await;
+
+    invocation
+        .say(ctx, format!("Speed set to {}x.", speed))
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[max_args(1)]
+#[description = "Show the pitch, or change it without changing the speed."]
+#[usage = "[0.5-2.0]"]
+#[example = "0.8"]
+#[bucket = "control"]
+async fn pitch(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let pitch = if args.is_empty() {
+        None
+    } else {
+        Some(parse_factor(args.message(), "pitch")?)
+    };
+
+    Ok(_pitch(ctx, &Invocation::Message(msg), pitch).await?)
+}
+
+pub async fn _pitch(
+    ctx: &Context,
+    invocation: &Invocation<'_>,
+    pitch: Option<f64>,
+) -> CaitlynResult {
+    let guild_id = match invocation.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    let pitch = match pitch {
+        Some(pitch) => check_factor(pitch, "pitch")?,
+        None => return show_filters(ctx, invocation, guild_id).await,
+    };
+
+    check_dj(ctx, invocation).await?;
+    apply_filters(ctx, guild_id, |filters| filters.pitch = pitch).await;
+
+    invocation
+        .say(ctx, format!("Pitch set to {}x.", pitch))
+        .await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[aliases(equalizer, equaliser)]
+#[description = "Show the equalizer, apply a preset, set bands in Hz:dB, or turn it off."]
+#[usage = "[bass|treble|vocal|pop|rock|off|<Hz:dB>...]"]
+#[example = "60:6 170:3 6000:-2"]
+#[bucket = "control"]
+async fn eq(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let value = if args.is_empty() {
+        None
+    } else {
+        Some(args.message().to_string())
+    };
+
+    Ok(_eq(ctx, &Invocation::Message(msg), value).await?)
+}
+
+pub async fn _eq(
+    ctx: &Context,
+    invocation: &Invocation<'_>,
+    value: Option<String>,
+) -> CaitlynResult {
+    let guild_id = match invocation.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    let value = match value {
+        Some(value) => value,
+        None => return show_filters(ctx, invocation, guild_id).await,
+    };
+
+    let bands = if value.trim().eq_ignore_ascii_case("off") {
+        Vec::new()
+    } else {
+        let bands = parse_equalizer(&value.to_lowercase()).map_err(|why| {
+            Error::BadArgument(format!("{}. Presets: {}.", why, EQ_PRESETS.join(", ")))
+        })?;
+        if bands.is_empty() {
+            return Err(Error::BadArgument(format!(
+                "Pick a preset ({}), or bands like `60:4 1000:-2`.",
+                EQ_PRESETS.join(", ")
+            )));
+        }
+        bands
+    };
+
+    check_dj(ctx, invocation).await?;
+    let filters = apply_filters(ctx, guild_id, |filters| filters.equalizer = bands).await;
+
+    let reply = if filters.equalizer.is_empty() {
+        String::from("Turned the equalizer off.")
+    } else {
+        format!("Filters: {}.", filters.describe())
+    };
+    invocation.say(ctx, reply).await?;
+
+    Ok(())
+}
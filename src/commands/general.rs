@@ -1,13 +1,149 @@
+use crate::commands::invocation::Invocation;
+use crate::error::{CaitlynResult, Error};
+use crate::storage::{guild_settings, SETTING_KEYS};
+use crate::suggest::{closest, command_names, find_command};
+use crate::{AudioFiles, Guilds, GROUPS};
 use itertools::enumerate;
-use serenity::framework::standard::macros::command;
-use serenity::framework::standard::CommandResult;
+use serenity::builder::CreateEmbed;
+use serenity::framework::standard::macros::{command, help};
+use serenity::framework::standard::{
+    help_commands, Args, Command, CommandGroup, CommandResult, HelpOptions,
+};
 use serenity::model::prelude::*;
 use serenity::prelude::*;
 use std::cmp::Ordering;
+use std::collections::HashSet;
+
+#[help]
+#[individual_command_tip = "Use `help <command>` for the details of one command, e.g. `help play`."]
+#[command_not_found_text = "There is no command called `{}`."]
+#[max_levenshtein_distance(3)]
+#[lacking_permissions = "Hide"]
+#[lacking_role = "Hide"]
+#[lacking_ownership = "Hide"]
+#[lacking_conditions = "Hide"]
+#[wrong_channel = "Strike"]
+#[strikethrough_commands_tip_in_guild = ""]
+async fn help(
+    context: &Context,
+    msg: &Message,
+    args: Args,
+    help_options: &'static HelpOptions,
+    groups: &[&'static CommandGroup],
+    owners: HashSet<UserId>,
+) -> CommandResult {
+    help_commands::with_embeds(context, msg, args, help_options, groups, owners).await?;
+
+    Ok(())
+}
+
+/// The slash equivalent of `help`, which lists the commands or shows how to
+/// use one of them.
+pub async fn _help(
+    ctx: &Context,
+    invocation: &Invocation<'_>,
+    name: Option<String>,
+) -> CaitlynResult {
+    let (config, settings) = guild_settings(ctx, invocation.guild_id()).await;
+    let prefix = settings.prefix(&config);
+    let owner = config.owners.contains(&invocation.author().id);
+    // Hide what the caller can't use, as the prefix help does.
+    let shown = |command: &Command| {
+        let required = command.options.required_permissions;
+        command.options.help_available
+            && (owner
+                || (!command.options.owners_only
+                    && (required.is_empty() || invocation.has_permissions(ctx, required))))
+    };
+
+    let mut embed = CreateEmbed::default();
+    embed.colour(settings.colour(&config));
+    match name
+        .as_deref()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        Some(name) => {
+            let name = name.trim_start_matches('/').to_lowercase();
+            let command = match find_command(&GROUPS, &name).filter(|command| shown(command)) {
+                Some(command) => command,
+                None => {
+                    let names = command_names(&GROUPS);
+                    return Err(Error::BadArgument(match closest(&name, &names) {
+                        Some(suggestion) => format!(
+                            "There is no command called `{}`. Did you mean `{}`?",
+                            name, suggestion
+                        ),
+                        None => format!("There is no command called `{}`.", name),
+                    }));
+                }
+            };
+
+            let names = command.options.names;
+            embed
+                .title(names[0])
+                .description(command.options.desc.unwrap_or_default())
+                .field(
+                    "Usage:",
+                    format!(
+                        "`{}{} {}`",
+                        prefix,
+                        names[0],
+                        command.options.usage.unwrap_or_default()
+                    ),
+                    false,
+                );
+            if names.len() > 1 {
+                let aliases: Vec<String> = names[1..]
+                    .iter()
+                    .map(|alias| format!("`{}`", alias))
+                    .collect();
+                embed.field("Aliases:", aliases.join(", "), false);
+            }
+            if !command.options.examples.is_empty() {
+                let examples: Vec<String> = command
+                    .options
+                    .examples
+                    .iter()
+                    .map(|example| format!("`{}{} {}`", prefix, names[0], example))
+                    .collect();
+                embed.field("Examples:", examples.join("\n"), false);
+            }
+        }
+        None => {
+            embed.title("Commands").footer(|f| {
+                f.text(format!(
+                    "Use /help <command> for the details of one command. Every command also works with the {} prefix.",
+                    prefix
+                ))
+            });
+            for group in GROUPS {
+                let commands: Vec<String> = group
+                    .options
+                    .commands
+                    .iter()
+                    .filter(|command| shown(command))
+                    .map(|command| format!("`{}`", command.options.names[0]))
+                    .collect();
+                if !commands.is_empty() {
+                    embed.field(group.name, commands.join(", "), false);
+                }
+            }
+        }
+    }
+    invocation.send_embed(ctx, embed).await?;
+
+    Ok(())
+}
 
 #[command]
+#[description = "Check that the bot is responding."]
 async fn ping(context: &Context, msg: &Message) -> CommandResult {
-    msg.channel_id.say(&context.http, "Pong!").await?;
+    Ok(_ping(context, &Invocation::Message(msg)).await?)
+}
+
+pub async fn _ping(context: &Context, invocation: &Invocation<'_>) -> CaitlynResult {
+    invocation.say(context, "Pong!").await?;
 
     Ok(())
 }
@@ -22,14 +158,27 @@ fn tuplesort(a: &(&UserId, i64), b: &(&UserId, i64)) -> Ordering {
 }
 
 #[command]
+#[description = "Show when a member joined, their roles and status."]
+#[usage = "[@member]"]
+#[example = "@Caitlyn"]
+#[bucket = "embeds"]
 async fn userinfo(ctx: &Context, msg: &Message) -> CommandResult {
     let user = match msg.mentions.first() {
         Some(user) => user,
         None => &msg.author,
     };
 
-    match msg.guild(&ctx.cache) {
+    Ok(_userinfo(ctx, &Invocation::Message(msg), user).await?)
+}
+
+pub async fn _userinfo(ctx: &Context, invocation: &Invocation<'_>, user: &User) -> CaitlynResult {
+    match invocation
+        .guild_id()
+        .and_then(|guild_id| guild_id.to_guild_cached(&ctx.cache))
+    {
         Some(guild) => {
+            let (config, settings) = guild_settings(ctx, Some(guild.id)).await;
+
             //Member position
 
             let mut membervec: Vec<(&UserId, i64)> = Vec::new();
@@ -73,73 +222,398 @@ async fn userinfo(ctx: &Context, msg: &Message) -> CommandResult {
                         }
                     }
 
-                    msg.channel_id
-                        .send_message(&ctx.http, |m| {
-                            m.embed(|e| {
-                                e.color(0xFFC0CB)
-                                    .description(format!(
-                                        "{} chilling in {} mode",
-                                        &user.name,
-                                        match guild.presences.get(&user.id) {
-                                            Some(presence) => {
-                                                presence.status.name()
-                                            }
-                                            None => {
-                                                "offline"
-                                            }
-                                        }
-                                    ))
-                                    .timestamp(msg.timestamp)
-                                    .field(
-                                        "Nick",
-                                        match &member.nick {
-                                            None => "None",
-                                            Some(nick) => nick,
-                                        },
-                                        true,
-                                    )
-                                    .field("Member No.", &member_position.to_string(), true)
-                                    .field(
-                                        "Account Created",
-                                        &member
-                                            .user
-                                            .created_at()
-                                            .format("%A, %d. %B %Y")
-                                            .to_string(),
-                                        true,
-                                    )
-                                    .field("Join Date", &formatted_date, true)
-                                    .field(
-                                        "Roles",
-                                        if rolenames.is_empty() {
-                                            "None"
-                                        } else {
-                                            &rolenames
-                                        },
-                                        true,
-                                    )
-                                    .field("User ID", &user.id.as_u64().to_string(), true)
-                                    .thumbnail(&user.face())
-                                    .author(|a| {
-                                        a.name(&user.name).icon_url(match &guild.icon_url() {
-                                            None => user.face(),
-                                            Some(icon) => icon.to_string(),
-                                        })
-                                    })
+                    let mut embed = CreateEmbed::default();
+                    embed
+                        .color(settings.colour(&config))
+                        .description(format!(
+                            "{} chilling in {} mode",
+                            &user.name,
+                            match guild.presences.get(&user.id) {
+                                Some(presence) => {
+                                    presence.status.name()
+                                }
+                                None => {
+                                    "offline"
+                                }
+                            }
+                        ))
+                        .timestamp(invocation.timestamp())
+                        .field(
+                            "Nick",
+                            match &member.nick {
+                                None => "None",
+                                Some(nick) => nick,
+                            },
+                            true,
+                        )
+                        .field("Member No.", &member_position.to_string(), true)
+                        .field(
+                            "Account Created",
+                            &member.user.created_at().format("%A, %d. %B %Y").to_string(),
+                            true,
+                        )
+                        .field("Join Date", &formatted_date, true)
+                        .field(
+                            "Roles",
+                            if rolenames.is_empty() {
+                                "None"
+                            } else {
+                                &rolenames
+                            },
+                            true,
+                        )
+                        .field("User ID", &user.id.as_u64().to_string(), true)
+                        .thumbnail(&user.face())
+                        .author(|a| {
+                            a.name(&user.name).icon_url(match &guild.icon_url() {
+                                None => user.face(),
+                                Some(icon) => icon.to_string(),
                             })
-                        })
-                        .await?;
+                        });
+
+                    invocation.send_embed(ctx, embed).await?;
                 }
                 None => {
-                    msg.channel_id
-                        .say(&ctx.http, "Failed to get member.")
-                        .await?;
+                    invocation.say(ctx, "Failed to get member.").await?;
                 }
             }
         }
         None => {
-            msg.channel_id.say(&ctx.http, "This command can only be used in a guild! Reason:||Online status's and roles are now linked to guilds.||").await?;
+            invocation.say(ctx, "This command can only be used in a guild! Reason:||Online status's and roles are now linked to guilds.||").await?;
         }
     }
     Ok(())
 }
+
+pub enum ConfigAction {
+    Show,
+    Set(String, String),
+    Reset(String),
+}
+
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+#[sub_commands(config_set, config_reset)]
+#[description = "Show this server's settings."]
+#[usage = "[set <key> <value> | reset <key>]"]
+#[example = "set queue_page_size 15"]
+async fn config(ctx: &Context, msg: &Message) -> CommandResult {
+    Ok(_config(ctx, &Invocation::Message(msg), ConfigAction::Show).await?)
+}
+
+#[command("set")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+#[min_args(2)]
+#[description = "Override a setting for this server."]
+#[usage = "<key> <value>"]
+#[example = "colour #FFC0CB"]
+async fn config_set(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let key = args
+        .single::<String>()
+        .map_err(|_| Error::BadArgument(String::from("Which setting should I change?")))?;
+    let value = args.rest().to_string();
+
+    Ok(_config(
+        ctx,
+        &Invocation::Message(msg),
+        ConfigAction::Set(key, value),
+    )
+    .await?)
+}
+
+#[command("reset")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+#[num_args(1)]
+#[description = "Go back to the default value of a setting."]
+#[usage = "<key>"]
+#[example = "queue_timeout"]
+async fn config_reset(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let key = args
+        .single::<String>()
+        .map_err(|_| Error::BadArgument(String::from("Which setting should I reset?")))?;
+
+    Ok(_config(ctx, &Invocation::Message(msg), ConfigAction::Reset(key)).await?)
+}
+
+pub async fn _config(
+    ctx: &Context,
+    invocation: &Invocation<'_>,
+    action: ConfigAction,
+) -> CaitlynResult {
+    let guild_id = match invocation.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    // Slash command permissions can be changed by the server, so check here
+    // too.
+    if !invocation.has_permissions(ctx, Permissions::MANAGE_GUILD) {
+        return Err(Error::PermissionDenied(String::from(
+            "You need the Manage Server permission to see or change the settings.",
+        )));
+    }
+
+    let store = {
+        let data_read = ctx.data.read().await;
+
+        data_read
+            .get::<Guilds>()
+            .expect("Expected guild settings in TypeMap.")
+            .clone()
+    };
+
+    let result = match &action {
+        ConfigAction::Show => Ok(()),
+        ConfigAction::Set(key, value) => store
+            .write()
+            .await
+            .update(guild_id, |settings| settings.set(key, value)),
+        ConfigAction::Reset(key) => store
+            .write()
+            .await
+            .update(guild_id, |settings| settings.reset(key)),
+    };
+
+    let (config, settings) = guild_settings(ctx, Some(guild_id)).await;
+
+    match (action, result) {
+        (ConfigAction::Show, _) => {
+            let mut embed = CreateEmbed::default();
+            embed
+                .colour(settings.colour(&config))
+                .title("Server settings")
+                .footer(|f| f.text(format!("Keys: {}", SETTING_KEYS.join(", "))));
+            for (key, value) in settings.describe(&config) {
+                embed.field(key, value, true);
+            }
+            invocation.send_embed(ctx, embed).await?;
+        }
+        (ConfigAction::Set(key, value), Ok(())) => {
+            invocation
+                .say(ctx, format!("Set `{}` to `{}`.", key, value))
+                .await?;
+        }
+        (ConfigAction::Reset(key), Ok(())) => {
+            invocation
+                .say(ctx, format!("Reset `{}` to the default.", key))
+                .await?;
+        }
+        (ConfigAction::Set(key, _), Err(why)) | (ConfigAction::Reset(key), Err(why)) => {
+            return Err(Error::BadArgument(format!(
+                "Could not change `{}`: {}.",
+                key, why
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+pub enum PrefixAction {
+    Show,
+    Set(String),
+    Reset,
+}
+
+#[command]
+#[only_in(guilds)]
+#[sub_commands(prefix_set, prefix_reset)]
+#[description = "Show the prefix for this server."]
+#[usage = "[set <prefix> | reset]"]
+#[example = "set !"]
+async fn prefix(ctx: &Context, msg: &Message) -> CommandResult {
+    Ok(_prefix(ctx, &Invocation::Message(msg), PrefixAction::Show).await?)
+}
+
+#[command("set")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+#[num_args(1)]
+#[description = "Change the prefix for this server."]
+#[usage = "<prefix>"]
+#[example = "!"]
+async fn prefix_set(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let prefix = args
+        .single::<String>()
+        .map_err(|_| Error::BadArgument(String::from("What should the new prefix be?")))?;
+
+    Ok(_prefix(ctx, &Invocation::Message(msg), PrefixAction::Set(prefix)).await?)
+}
+
+#[command("reset")]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+#[description = "Go back to the default prefix."]
+async fn prefix_reset(ctx: &Context, msg: &Message) -> CommandResult {
+    Ok(_prefix(ctx, &Invocation::Message(msg), PrefixAction::Reset).await?)
+}
+
+pub async fn _prefix(
+    ctx: &Context,
+    invocation: &Invocation<'_>,
+    action: PrefixAction,
+) -> CaitlynResult {
+    let guild_id = match invocation.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    if !matches!(action, PrefixAction::Show)
+        && !invocation.has_permissions(ctx, Permissions::MANAGE_GUILD)
+    {
+        return Err(Error::PermissionDenied(String::from(
+            "You need the Manage Server permission to change the prefix.",
+        )));
+    }
+
+    let store = {
+        let data_read = ctx.data.read().await;
+
+        data_read
+            .get::<Guilds>()
+            .expect("Expected guild settings in TypeMap.")
+            .clone()
+    };
+
+    let result = match &action {
+        PrefixAction::Show => Ok(()),
+        PrefixAction::Set(prefix) => store
+            .write()
+            .await
+            .update(guild_id, |settings| settings.set_prefix(prefix)),
+        PrefixAction::Reset => store.write().await.update(guild_id, |settings| {
+            settings.prefix = None;
+            Ok(())
+        }),
+    };
+
+    let (config, settings) = guild_settings(ctx, Some(guild_id)).await;
+    let current = settings.prefix(&config);
+
+    let reply = match (action, result) {
+        (PrefixAction::Show, _) => format!(
+            "My prefix here is `{}`, and you can always mention me instead.",
+            current
+        ),
+        (PrefixAction::Set(_), Ok(())) => format!("Prefix set to `{}`.", current),
+        (PrefixAction::Reset, Ok(())) => format!("Prefix reset to `{}`.", current),
+        (_, Err(why)) => {
+            return Err(Error::BadArgument(format!(
+                "Could not change the prefix: {}.",
+                why
+            )))
+        }
+    };
+    invocation.say(ctx, reply).await?;
+
+    Ok(())
+}
+
+pub enum CacheAction {
+    Show,
+    Purge,
+}
+
+/// How many cached tracks `cache` lists.
+const CACHE_LISTED: usize = 10;
+
+fn format_size(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+#[command]
+#[owners_only]
+#[sub_commands(cache_purge)]
+#[description = "Show what is in the audio cache."]
+#[usage = "[purge]"]
+async fn cache(ctx: &Context, msg: &Message) -> CommandResult {
+    Ok(_cache(ctx, &Invocation::Message(msg), CacheAction::Show).await?)
+}
+
+#[command("purge")]
+#[owners_only]
+#[description = "Delete every track in the audio cache."]
+async fn cache_purge(ctx: &Context, msg: &Message) -> CommandResult {
+    Ok(_cache(ctx, &Invocation::Message(msg), CacheAction::Purge).await?)
+}
+
+pub async fn _cache(
+    ctx: &Context,
+    invocation: &Invocation<'_>,
+    action: CacheAction,
+) -> CaitlynResult {
+    let (config, settings) = guild_settings(ctx, invocation.guild_id()).await;
+    if !config.owners.contains(&invocation.author().id) {
+        return Err(Error::PermissionDenied(String::from(
+            "Only the bot owners can use that.",
+        )));
+    }
+
+    let audio_cache = {
+        let data_read = ctx.data.read().await;
+
+        data_read
+            .get::<AudioFiles>()
+            .expect("Expected audio cache in TypeMap.")
+            .clone()
+    };
+
+    match action {
+        CacheAction::Show => {
+            let audio_cache = audio_cache.read().await;
+            if !audio_cache.enabled() {
+                invocation
+                    .say(ctx, "The audio cache is turned off.")
+                    .await?;
+                return Ok(());
+            }
+
+            let entries = audio_cache.entries();
+            let lines: Vec<String> = entries
+                .iter()
+                .take(CACHE_LISTED)
+                .map(|(key, entry)| {
+                    format!(
+                        "`{}` {}, {} hits",
+                        key,
+                        format_size(entry.bytes),
+                        entry.hits
+                    )
+                })
+                .collect();
+
+            let mut embed = CreateEmbed::default();
+            embed
+                .colour(settings.colour(&config))
+                .title("Audio cache")
+                .field("Tracks:", entries.len().to_string(), true)
+                .field(
+                    "Size:",
+                    format!(
+                        "{} of {}",
+                        format_size(audio_cache.total_bytes()),
+                        format_size(audio_cache.max_bytes())
+                    ),
+                    true,
+                );
+            if !lines.is_empty() {
+                embed.field("Recently played:", lines.join("\n"), false);
+            }
+            invocation.send_embed(ctx, embed).await?;
+        }
+        CacheAction::Purge => {
+            let (tracks, bytes) = audio_cache.write().await.purge();
+            invocation
+                .say(
+                    ctx,
+                    format!("Deleted {} cached tracks ({}).", tracks, format_size(bytes)),
+                )
+                .await?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,165 @@
+use serenity::builder::{CreateComponents, CreateEmbed};
+use serenity::client::Context;
+use serenity::model::application::interaction::application_command::ApplicationCommandInteraction;
+use serenity::model::application::interaction::message_component::MessageComponentInteraction;
+use serenity::model::prelude::*;
+use serenity::Result as SerenityResult;
+
+/// Where a command came from, so prefix and slash commands and buttons can
+/// share one implementation. Slash commands are deferred and buttons are
+/// acknowledged before they are run, so every reply to them is sent as a
+/// followup.
+#[derive(Clone, Copy)]
+pub enum Invocation<'a> {
+    Message(&'a Message),
+    Slash(&'a ApplicationCommandInteraction),
+    Component(&'a MessageComponentInteraction),
+}
+
+impl<'a> Invocation<'a> {
+    pub fn guild_id(&self) -> Option<GuildId> {
+        match self {
+            Invocation::Message(msg) => msg.guild_id,
+            Invocation::Slash(command) => command.guild_id,
+            Invocation::Component(component) => component.guild_id,
+        }
+    }
+
+    pub fn channel_id(&self) -> ChannelId {
+        match self {
+            Invocation::Message(msg) => msg.channel_id,
+            Invocation::Slash(command) => command.channel_id,
+            Invocation::Component(component) => component.channel_id,
+        }
+    }
+
+    pub fn author(&self) -> &'a User {
+        match self {
+            Invocation::Message(msg) => &msg.author,
+            Invocation::Slash(command) => &command.user,
+            Invocation::Component(component) => &component.user,
+        }
+    }
+
+    pub fn timestamp(&self) -> Timestamp {
+        match self {
+            Invocation::Message(msg) => msg.timestamp,
+            Invocation::Slash(command) => command.id.created_at(),
+            Invocation::Component(component) => component.id.created_at(),
+        }
+    }
+
+    /// Whether the author has `permissions` in the guild, counting
+    /// administrators and the guild owner as having everything.
+    pub fn has_permissions(&self, ctx: &Context, permissions: Permissions) -> bool {
+        let granted = match self {
+            Invocation::Message(msg) => msg
+                .guild_id
+                .and_then(|guild_id| guild_id.to_guild_cached(&ctx.cache))
+                .and_then(|guild| {
+                    let member = guild.members.get(&msg.author.id)?;
+                    Some(guild.member_permissions(member))
+                }),
+            Invocation::Slash(command) => command
+                .member
+                .as_ref()
+                .and_then(|member| member.permissions),
+            Invocation::Component(component) => component
+                .member
+                .as_ref()
+                .and_then(|member| member.permissions),
+        };
+
+        match granted {
+            Some(granted) => granted.administrator() || granted.contains(permissions),
+            None => false,
+        }
+    }
+
+    pub fn has_role(&self, ctx: &Context, role_id: RoleId) -> bool {
+        match self {
+            Invocation::Message(msg) => match &msg.member {
+                Some(member) => member.roles.contains(&role_id),
+                None => msg
+                    .guild_id
+                    .and_then(|guild_id| guild_id.to_guild_cached(&ctx.cache))
+                    .and_then(|guild| {
+                        let member = guild.members.get(&msg.author.id)?;
+                        Some(member.roles.contains(&role_id))
+                    })
+                    .unwrap_or(false),
+            },
+            Invocation::Slash(command) => command
+                .member
+                .as_ref()
+                .map_or(false, |member| member.roles.contains(&role_id)),
+            Invocation::Component(component) => component
+                .member
+                .as_ref()
+                .map_or(false, |member| member.roles.contains(&role_id)),
+        }
+    }
+
+    pub async fn say(&self, ctx: &Context, content: impl ToString) -> SerenityResult<Message> {
+        match self {
+            Invocation::Message(msg) => msg.channel_id.say(&ctx.http, content.to_string()).await,
+            Invocation::Slash(command) => {
+                command
+                    .create_followup_message(&ctx.http, |f| f.content(content.to_string()))
+                    .await
+            }
+            Invocation::Component(component) => {
+                component
+                    .create_followup_message(&ctx.http, |f| f.content(content.to_string()))
+                    .await
+            }
+        }
+    }
+
+    pub async fn send_embed(&self, ctx: &Context, embed: CreateEmbed) -> SerenityResult<Message> {
+        self.send(ctx, embed, None).await
+    }
+
+    pub async fn send(
+        &self,
+        ctx: &Context,
+        embed: CreateEmbed,
+        components: Option<CreateComponents>,
+    ) -> SerenityResult<Message> {
+        match self {
+            Invocation::Message(msg) => {
+                msg.channel_id
+                    .send_message(&ctx.http, |m| {
+                        m.set_embed(embed);
+                        if let Some(components) = components {
+                            m.set_components(components);
+                        }
+                        m
+                    })
+                    .await
+            }
+            Invocation::Slash(command) => {
+                command
+                    .create_followup_message(&ctx.http, |f| {
+                        f.add_embed(embed);
+                        if let Some(components) = components {
+                            f.set_components(components);
+                        }
+                        f
+                    })
+                    .await
+            }
+            Invocation::Component(component) => {
+                component
+                    .create_followup_message(&ctx.http, |f| {
+                        f.add_embed(embed);
+                        if let Some(components) = components {
+                            f.set_components(components);
+                        }
+                        f
+                    })
+                    .await
+            }
+        }
+    }
+}
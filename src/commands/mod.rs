@@ -1,2 +1,8 @@
+pub mod chapters;
+pub mod controls;
+pub mod filters;
 pub mod general;
+pub mod invocation;
 pub mod music;
+pub mod player;
+pub mod slash;
@@ -2,24 +2,55 @@ use futures::StreamExt;
 use itertools::enumerate;
 use serenity::{
     async_trait,
+    builder::{CreateComponents, CreateEmbed},
     client::Context,
-    framework::standard::{macros::command, Args, CommandResult},
+    framework::standard::{
+        macros::{check, command},
+        Args, CommandOptions, CommandResult, Reason,
+    },
     http::Http,
-    model::{channel::Message, prelude::ChannelId},
-    Result as SerenityResult,
+    model::{
+        application::{component::ButtonStyle, interaction::InteractionResponseType},
+        channel::Message,
+        prelude::ChannelId,
+    },
 };
 
-use crate::{Queue, Track};
+use crate::chapters::{current_chapter, Chapter, Chapters};
+use crate::commands::controls::{
+    check_dj, control_buttons, current_track, playback_state, set_paused, set_volume,
+    shuffle_queue, toggle_loop, PlaybackState, VOLUME_STEP,
+};
+use crate::commands::invocation::Invocation;
+use crate::commands::player::update_player;
+use crate::config::Config;
+use crate::error::{CaitlynResult, Error};
+use crate::failures::{self, Retries, TrackFailure};
+use crate::filters::{guild_filters, AudioFilters};
+use crate::lyrics::LyricsQuery;
+use crate::pagination::{
+    paginate_lines, split_pages, truncate, EMBED_AUTHOR_LIMIT, EMBED_DESCRIPTION_LIMIT,
+    EMBED_TOTAL_LIMIT,
+};
+use crate::reconnect::VoiceWatcher;
+use crate::segments::{describe_skipped, Segment, SegmentSkipper, SkippedSegments, SEGMENT_TICK};
+use crate::source::YtdlSource;
+use crate::storage::{guild_settings, now, settings_from_data, GuildSettings};
+use crate::title::TitleRules;
+use crate::transitions::{Transitions, TRANSITION_TICK};
+use crate::util::{format_duration, video_id};
+use crate::{AudioFiles, Loudness, Lyrics, Queue, Resolver, Segments, Titles, Track};
 use regex::Regex;
 use serenity::model::id::GuildId;
 use serenity::model::mention::Mentionable;
 use serenity::prelude::TypeMap;
+use songbird::tracks::{PlayMode, TrackHandle};
 use songbird::{
-    input::restartable::Restartable, Event, EventContext, EventHandler as VoiceEventHandler,
-    TrackEvent,
+    Call, CoreEvent, Event, EventContext, EventHandler as VoiceEventHandler, Songbird, TrackEvent,
 };
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::{oneshot, RwLock};
 
 #[allow(dead_code)]
 struct TrackEndNotifier {
@@ -32,7 +63,7 @@ struct TrackEndNotifier {
 #[async_trait]
 impl VoiceEventHandler for TrackEndNotifier {
     async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
-        if let EventContext::Track(_track_list) = ctx {
+        if let EventContext::Track(track_list) = ctx {
             let queue_lock = {
                 let data_read = self.data.read().await;
                 data_read
@@ -40,58 +71,518 @@ impl VoiceEventHandler for TrackEndNotifier {
                     .expect("Expected Queue in TypeMap.")
                     .clone()
             };
-            {
-                let mut queue = queue_lock.write().await;
-                if !queue.is_empty() {
-                    queue.remove(0);
+            let ended: Vec<u128> = track_list
+                .iter()
+                .map(|(_, handle)| handle.uuid().as_u128())
+                .collect();
+            queue_lock
+                .write()
+                .await
+                .retain(|track| !ended.contains(&track.handle_id));
+            update_player(&self.http, &self.data, self.guild_id).await;
+        }
+
+        None
+    }
+}
+
+struct TrackStartNotifier {
+    http: Arc<Http>,
+    data: Arc<RwLock<TypeMap>>,
+    guild_id: GuildId,
+}
+
+#[async_trait]
+impl VoiceEventHandler for TrackStartNotifier {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        if let EventContext::Track(_track_list) = ctx {
+            update_player(&self.http, &self.data, self.guild_id).await;
+        }
+
+        None
+    }
+}
+
+/// Restarts a track that stopped with an error where it stopped, and skips it
+/// with a message once it has failed more than `limits.track_retries` times.
+struct TrackErrorNotifier {
+    chan_id: ChannelId,
+    http: Arc<Http>,
+    data: Arc<RwLock<TypeMap>>,
+    manager: Arc<Songbird>,
+    guild_id: GuildId,
+}
+
+impl TrackErrorNotifier {
+    /// Takes `track` out of songbird's queue, which only moves on from tracks
+    /// that end, returning where it was.
+    async fn dequeue(&self, track: &TrackHandle) -> Option<usize> {
+        let handler_lock = self.manager.get(self.guild_id)?;
+        let handler = handler_lock.lock().await;
+
+        handler.queue().modify_queue(|queued| {
+            let index = queued.iter().position(|item| item.uuid() == track.uuid())?;
+            queued.remove(index);
+            Some(index)
+        })
+    }
+
+    /// Plays whatever is at the head of the queue now.
+    async fn play_next(&self) {
+        if let Some(handler_lock) = self.manager.get(self.guild_id) {
+            let _ = handler_lock.lock().await.queue().resume();
+        }
+    }
+
+    /// Queues `failed` again at `index`, where it was, from `position`.
+    async fn retry(
+        &self,
+        failed: &Track,
+        index: usize,
+        position: Duration,
+        attempt: u32,
+    ) -> CaitlynResult {
+        let handler_lock = self.manager.get(self.guild_id).ok_or(Error::NotConnected)?;
+        let mut handler = handler_lock.lock().await;
+
+        let (_, track) = enqueue(
+            &self.data,
+            &mut handler,
+            self.guild_id,
+            &failed.url,
+            true,
+            failed.requester.to_owned(),
+            Placement::At(index),
+        )
+        .await?;
+        track.typemap().write().await.insert::<Retries>(attempt);
+        if !position.is_zero() {
+            track
+                .seek_time(position)
+                .map_err(|_| Error::Source(String::from("it can't be resumed")))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl VoiceEventHandler for TrackErrorNotifier {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let (state, track) = match ctx {
+            EventContext::Track(tracks) => *tracks.first()?,
+            _ => return None,
+        };
+        let mut reason = match &state.playing {
+            PlayMode::Errored(why) => why.to_string(),
+            _ => String::from("it stopped unexpectedly"),
+        };
+
+        let index = self.dequeue(track).await;
+        let (config, queue_lock) = {
+            let data_read = self.data.read().await;
+            let (config, _) = settings_from_data(&data_read, Some(self.guild_id)).await;
+            let queue_lock = data_read
+                .get::<Queue>()
+                .expect("Expected Queue in TypeMap.")
+                .clone();
+            (config, queue_lock)
+        };
+        let failed = {
+            let mut queue = queue_lock.write().await;
+            let id = track.uuid().as_u128();
+            queue
+                .iter()
+                .position(|queued| queued.handle_id == id)
+                .map(|position| queue.remove(position))
+        };
+        let failed = match failed {
+            Some(failed) => failed,
+            None => {
+                if index == Some(0) {
+                    self.play_next().await;
                 }
+                update_player(&self.http, &self.data, self.guild_id).await;
+                return None;
+            }
+        };
+
+        let attempt = track
+            .typemap()
+            .read()
+            .await
+            .get::<Retries>()
+            .copied()
+            .unwrap_or(0)
+            + 1;
+        let mut skipped = attempt > config.limits.track_retries || failed.url == "None";
+        if !skipped {
+            let retried = self
+                .retry(&failed, index.unwrap_or(0), state.position, attempt)
+                .await;
+            if let Err(why) = retried {
+                reason = format!("{}, and retrying failed: {}", reason, why);
+                skipped = true;
             }
         }
+        if skipped && index == Some(0) {
+            self.play_next().await;
+        }
+
+        let failure = TrackFailure {
+            guild_id: self.guild_id.0,
+            url: failed.url.to_owned(),
+            title: failed.title.to_owned(),
+            reason,
+            position: state.position.as_secs_f64(),
+            attempt,
+            skipped,
+            at: now(),
+        };
+        warn!("Track failed: {:?}", failure);
+        let message = format!("Skipped **{}**: {}.", failed.title, failure.reason);
+        failures::record(config.data_dir.clone(), failure);
+
+        if skipped {
+            let _ = self.chan_id.say(&self.http, message).await;
+        }
+        update_player(&self.http, &self.data, self.guild_id).await;
 
         None
     }
 }
 
-trait StripBetween {
-    fn strip_between(&self, first_delimiter: &str, second_delimiter: &str) -> Self;
+struct QueueView {
+    pages: Vec<String>,
+    now_playing: String,
+    url: String,
+    remaining: Option<Duration>,
+    /// Whether a live stream is queued, when `remaining` is unknown.
+    live: bool,
+    colour: u32,
+    icon_url: String,
 }
 
-impl StripBetween for String {
-    fn strip_between(&self, first_delimiter: &str, second_delimiter: &str) -> Self {
-        if self.contains(first_delimiter) && self.contains(second_delimiter) {
-            let start_bytes = self.find(first_delimiter).unwrap();
-            let end_bytes = self.find(second_delimiter).unwrap();
-            let removal = &self[start_bytes..(end_bytes + 1)];
-            self.replace(removal, "")
+const QUEUE_FOOTER_RESERVE: usize = 96;
+
+pub fn format_length(track: &Track) -> String {
+    match track.duration {
+        Some(duration) => format_duration(duration),
+        None if track.live => String::from("LIVE"),
+        None => String::from("?:??"),
+    }
+}
+
+async fn current_position(ctx: &Context, guild_id: GuildId) -> Duration {
+    let manager = songbird::get(ctx)
+        .await
+        .expect("Songbird Voice client placed in at initialisation.")
+        .clone();
+
+    let current = match manager.get(guild_id) {
+        Some(handler_lock) => handler_lock.lock().await.queue().current(),
+        None => None,
+    };
+
+    match current {
+        Some(handle) => match handle.get_info().await {
+            Ok(state) => state.position,
+            Err(_) => Duration::ZERO,
+        },
+        None => Duration::ZERO,
+    }
+}
+
+/// Returns how long until each queued track starts playing, with `None` once a
+/// live stream or a track of unknown length is ahead of it, followed by the
+/// total remaining time of the queue. Track lengths are scaled by the `speed`
+/// they play at.
+fn queue_etas(
+    playlist: &[Track],
+    elapsed: Duration,
+    speed: f64,
+) -> (Vec<Option<Duration>>, Option<Duration>) {
+    let mut etas = Vec::new();
+    let mut eta = Some(Duration::ZERO);
+
+    for (i, track) in enumerate(playlist.iter()) {
+        etas.push(eta);
+        let length = track.duration.map(|d| d.div_f64(speed));
+        let left = if i == 0 {
+            length.map(|d| d.saturating_sub(elapsed))
         } else {
-            self.to_string()
+            length
+        };
+        eta = eta.and_then(|eta| left.map(|left| eta + left));
+    }
+
+    (etas, eta)
+}
+
+async fn queue_view(ctx: &Context, guild_id: GuildId) -> Option<QueueView> {
+    let (config, settings) = guild_settings(ctx, Some(guild_id)).await;
+    let elapsed = current_position(ctx, guild_id).await;
+
+    let queue_lock = {
+        let data_read = ctx.data.read().await;
+
+        data_read
+            .get::<Queue>()
+            .expect("Expected queue in TypeMap.")
+            .clone()
+    };
+    let title_rules = {
+        let data_read = ctx.data.read().await;
+
+        data_read
+            .get::<Titles>()
+            .expect("Expected title rules in TypeMap.")
+            .clone()
+    };
+    let speed = guild_filters(&ctx.data, guild_id)
+        .await
+        .read()
+        .await
+        .speed_factor();
+    let playlist = queue_lock.read().await;
+
+    let current_track = playlist.first()?;
+    let now_playing = truncate(
+        &format!("Now playing: {}", &current_track.title),
+        EMBED_AUTHOR_LIMIT,
+    );
+
+    let (etas, remaining) = queue_etas(&playlist, elapsed, speed);
+
+    let mut lines: Vec<String> = Vec::new();
+    for (i, (track, eta)) in enumerate(playlist.iter().zip(etas)) {
+        let title = title_rules.clean(&track.title);
+        let plays_in = match (i, eta) {
+            (0, _) => format!("{}/{}", format_duration(elapsed), format_length(track)),
+            (_, Some(eta)) => format!("plays in {}", format_duration(eta)),
+            (_, None) => String::from("plays in unknown"),
+        };
+        lines.push(format!(
+            "{}: {} `[{}]` (Requested by: {}) - {}",
+            i + 1,
+            title,
+            format_length(track),
+            &track.requester,
+            plays_in
+        ));
+    }
+
+    let budget = EMBED_DESCRIPTION_LIMIT
+        .min(EMBED_TOTAL_LIMIT - now_playing.chars().count() - QUEUE_FOOTER_RESERVE);
+
+    Some(QueueView {
+        pages: paginate_lines(&lines, settings.queue_page_size(&config), budget),
+        now_playing,
+        url: current_track.url.to_owned(),
+        remaining,
+        live: playlist.iter().any(|track| track.live),
+        colour: settings.colour(&config),
+        icon_url: config.icon_url.to_owned(),
+    })
+}
+
+fn queue_embed(view: &QueueView, page: usize) -> CreateEmbed {
+    let remaining = match view.remaining {
+        Some(remaining) => format_duration(remaining),
+        None if view.live => String::from("unknown (live stream queued)"),
+        None => String::from("unknown"),
+    };
+
+    let mut embed = CreateEmbed::default();
+    embed
+        .colour(view.colour)
+        .author(|a| {
+            a.name(&view.now_playing)
+                .url(&view.url)
+                .icon_url(&view.icon_url)
+        })
+        .description(&view.pages[page - 1])
+        .footer(|f| {
+            f.text(format!(
+                "Page: {}/{} | Remaining: {}",
+                page,
+                view.pages.len(),
+                remaining
+            ))
+        });
+    embed
+}
+
+fn queue_buttons(
+    c: &mut CreateComponents,
+    page: usize,
+    pages: usize,
+    disabled: bool,
+) -> &mut CreateComponents {
+    c.create_action_row(|r| {
+        for (id, label, at_edge) in [
+            ("queue_first", "«", page <= 1),
+            ("queue_prev", "‹", page <= 1),
+            ("queue_next", "›", page >= pages),
+            ("queue_last", "»", page >= pages),
+        ] {
+            r.create_button(|b| {
+                b.custom_id(id)
+                    .label(label)
+                    .style(ButtonStyle::Secondary)
+                    .disabled(disabled || at_edge)
+            });
         }
+        r
+    })
+}
+
+/// Fails when the server limits music commands to other text channels.
+pub async fn check_music_channel(ctx: &Context, invocation: &Invocation<'_>) -> CaitlynResult {
+    let (_, settings) = guild_settings(ctx, invocation.guild_id()).await;
+
+    if settings.music_channel_allowed(invocation.channel_id()) {
+        return Ok(());
     }
+    Err(Error::WrongChannel(format!(
+        "Music commands go in {}.",
+        settings.music_channel_mentions()
+    )))
+}
+
+#[check]
+#[name = "MusicChannel"]
+#[check_in_help(false)]
+pub async fn music_channel(
+    ctx: &Context,
+    msg: &Message,
+    _: &mut Args,
+    _: &CommandOptions,
+) -> Result<(), Reason> {
+    if let Err(why) = check_music_channel(ctx, &Invocation::Message(msg)).await {
+        let (_, settings) = guild_settings(ctx, msg.guild_id).await;
+        if settings.delete_misplaced() {
+            if let Err(why) = msg.delete(ctx).await {
+                warn!("Failed to delete misplaced command: {:?}", why);
+            }
+        }
+        return Err(Reason::User(why.to_string()));
+    }
+
+    Ok(())
 }
 
 #[command]
 #[aliases(q, list, playlist)]
+#[max_args(1)]
+#[description = "Show the queue, with buttons to flip through the pages."]
+#[usage = "[page]"]
+#[example = "2"]
+#[bucket = "embeds"]
 async fn queue(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
-    let mut page_number = if args.is_empty() {
+    let requested_page = if args.is_empty() {
         1
     } else {
         match args.message().to_string().parse::<i32>() {
             Ok(n) => n,
             Err(_) => {
-                msg.channel_id
-                    .say(
-                        &ctx.http,
-                        format!(
-                            "({}) is not a valid page number.",
-                            args.message().to_string()
-                        ),
-                    )
-                    .await?;
-                1
+                return Err(Error::BadArgument(format!(
+                    "`{}` is not a page number. Usage: `queue [page]`",
+                    args.message()
+                ))
+                .into())
             }
         }
     };
 
+    Ok(_queue(ctx, &Invocation::Message(msg), requested_page as i64).await?)
+}
+
+pub async fn _queue(
+    ctx: &Context,
+    invocation: &Invocation<'_>,
+    requested_page: i64,
+) -> CaitlynResult {
+    let (config, settings) = guild_settings(ctx, invocation.guild_id()).await;
+
+    let guild_id = match invocation.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    let mut view = match queue_view(ctx, guild_id).await {
+        Some(view) => view,
+        None => {
+            invocation.say(ctx, "Nothing is currently playing.").await?;
+            return Ok(());
+        }
+    };
+    let mut page = requested_page.clamp(1, view.pages.len() as i64) as usize;
+
+    let mut buttons = CreateComponents::default();
+    queue_buttons(&mut buttons, page, view.pages.len(), false);
+    let mut message = invocation
+        .send(ctx, queue_embed(&view, page), Some(buttons))
+        .await?;
+
+    let mut interactions = message
+        .await_component_interactions(&ctx)
+        .timeout(settings.queue_timeout(&config))
+        .build();
+
+    while let Some(interaction) = interactions.next().await {
+        if settings.queue_invoker_only(&config) && interaction.user.id != invocation.author().id {
+            let _ = interaction
+                .create_interaction_response(&ctx.http, |r| {
+                    r.kind(InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|d| {
+                            d.content("Only the person who opened this queue can page it.")
+                                .ephemeral(true)
+                        })
+                })
+                .await;
+            continue;
+        }
+
+        view = match queue_view(ctx, guild_id).await {
+            Some(view) => view,
+            None => break,
+        };
+        page = match interaction.data.custom_id.as_str() {
+            "queue_first" => 1,
+            "queue_prev" => page.saturating_sub(1),
+            "queue_next" => page + 1,
+            "queue_last" => view.pages.len(),
+            _ => page,
+        }
+        .clamp(1, view.pages.len());
+
+        let _ = interaction
+            .create_interaction_response(&ctx.http, |r| {
+                r.kind(InteractionResponseType::UpdateMessage)
+                    .interaction_response_data(|d| {
+                        d.add_embed(queue_embed(&view, page))
+                            .components(|c| queue_buttons(c, page, view.pages.len(), false))
+                    })
+            })
+            .await;
+    }
+
+    let _ = message
+        .edit(&ctx.http, |m| {
+            m.components(|c| queue_buttons(c, page, view.pages.len(), true))
+        })
+        .await;
+
+    Ok(())
+}
+
+/// The reply to a single enqueued track at `position` in the queue, with how
+/// long until it plays.
+async fn enqueued_embed(ctx: &Context, guild_id: GuildId, position: usize) -> Option<CreateEmbed> {
+    let (config, settings) = guild_settings(ctx, Some(guild_id)).await;
+    let elapsed = current_position(ctx, guild_id).await;
+
     let queue_lock = {
         let data_read = ctx.data.read().await;
 
@@ -100,78 +591,133 @@ async fn queue(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
             .expect("Expected queue in TypeMap.")
             .clone()
     };
+    let speed = guild_filters(&ctx.data, guild_id)
+        .await
+        .read()
+        .await
+        .speed_factor();
     let playlist = queue_lock.read().await;
+    let track = playlist.get(position)?;
 
-    if playlist.is_empty() {
-        check_msg(
-            msg.channel_id
-                .say(&ctx.http, "Nothing is currently playing.")
-                .await,
-        );
-        return Ok(());
+    let heading = if position == 0 {
+        "Now playing"
+    } else {
+        "Added to queue"
+    };
+    let mut embed = CreateEmbed::default();
+    embed
+        .colour(settings.colour(&config))
+        .author(|a| {
+            a.name(truncate(
+                &format!("{}: {}", heading, track.title),
+                EMBED_AUTHOR_LIMIT,
+            ))
+            .url(&track.url)
+            .icon_url(&config.icon_url)
+        })
+        .field("Length:", format_length(track), true);
+    if track.thumbnail != "None" {
+        embed.thumbnail(&track.thumbnail);
     }
-    let mut description = String::new();
-    let mut pages: Vec<String> = Vec::new();
 
-    let current_track = playlist.iter().next().unwrap();
+    if position > 0 {
+        let (etas, _) = queue_etas(&playlist, elapsed, speed);
+        let plays_in = match etas.get(position).copied().flatten() {
+            Some(eta) => format_duration(eta),
+            None => String::from("unknown"),
+        };
+        embed
+            .field("Position in queue:", position.to_string(), true)
+            .field("Plays in:", plays_in, true);
+    }
 
-    for (i, track) in enumerate(playlist.iter()) {
-        let mut title = track.title.to_owned();
-        title = title.strip_between("(", ")");
-        title = title.strip_between("[", "]");
+    Some(embed)
+}
 
-        description.push_str(&*format!("{}", i + 1));
-        description.push(':');
-        description.push(' ');
-        description.push_str(&title);
-        description.push(' ');
-        description.push_str(&*format!("(Requested by: {})", &track.requester));
-        description.push('\n');
-        if description.matches('\n').count() > 10 {
-            pages.push(description.clone());
-            description = String::new();
-        }
-    }
+/// Where a new track goes in the queue.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    /// After everything else.
+    Back,
+    /// Right after the current track.
+    Next,
+    /// In place of the current track, which is paused and plays next.
+    Now,
+    /// At an index of the queue, where a failed track was. At 0 it plays like
+    /// `Now`.
+    At(usize),
+}
 
-    if pages.is_empty() || !description.is_empty() {
-        pages.push(description);
-    }
+/// Looks up the segments of `track` to skip in the background, and skips
+/// them as it plays.
+async fn skip_segments(
+    data: &RwLock<TypeMap>,
+    track: &TrackHandle,
+    filters: Arc<RwLock<AudioFilters>>,
+) {
+    let video_id = match track.metadata().source_url.as_deref().and_then(video_id) {
+        Some(video_id) => video_id.to_string(),
+        None => return,
+    };
+    let provider = {
+        let data_read = data.read().await;
+        data_read
+            .get::<Segments>()
+            .expect("Expected segment provider in TypeMap.")
+            .clone()
+    };
 
-    if page_number <= 0 {
-        page_number = 1
-    }
-    if page_number > pages.len() as i32 {
-        page_number = pages.len() as i32
-    }
+    let track = track.clone();
+    tokio::spawn(async move {
+        let segments = match provider.segments(&video_id).await {
+            Ok(segments) if !segments.is_empty() => segments,
+            Ok(_) => return,
+            Err(why) => {
+                warn!("Failed to look up segments of {}: {:?}", video_id, why);
+                return;
+            }
+        };
 
-    let page = match pages.get((page_number - 1) as usize) {
-        Some(p) => p,
-        None => pages.get(0).unwrap(),
+        let _ = track.add_event(
+            Event::Periodic(SEGMENT_TICK, None),
+            SegmentSkipper { segments, filters },
+        );
+    });
+}
+
+/// Enqueues one video and replies with where it landed in the queue.
+async fn play_youtube_video(
+    ctx: &Context,
+    invocation: &Invocation<'_>,
+    query: String,
+    is_url: bool,
+    placement: Placement,
+) -> CaitlynResult {
+    let guild_id = match invocation.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
     };
 
-    check_msg(
-        msg.channel_id
-            .send_message(&ctx.http, |m| {
-                m.embed(|e| {
-                    e.author(|a| {
-                        a.name(format!("Now playing: {}", &current_track.title))
-                            .url(&current_track.url)
-                            .icon_url("https://i.imgur.com/vVvNHcj.png")
-                    })
-                    .description(page)
-                    .footer(|f| f.text(format!("Page: {}/{}", page_number, pages.len())))
-                })
-            })
-            .await,
-    );
+    let position = play_youtube_video_url(ctx, invocation, query, is_url, placement).await?;
+    if let Some(embed) = enqueued_embed(ctx, guild_id, position).await {
+        invocation.send_embed(ctx, embed).await?;
+    }
 
     Ok(())
 }
 
-
-async fn play_youtube_video_url(ctx: &Context, msg: &Message, query: String, is_url: bool, ) -> CommandResult {
-    let guild = msg.guild(&ctx.cache).unwrap();
-    let guild_id = guild.id;
+/// Enqueues one video at `placement`, returning its position in the queue.
+async fn play_youtube_video_url(
+    ctx: &Context,
+    invocation: &Invocation<'_>,
+    query: String,
+    is_url: bool,
+    placement: Placement,
+) -> CaitlynResult<usize> {
+    let guild_id = match invocation.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Err(Error::GuildOnly),
+    };
     let manager = songbird::get(ctx)
         .await
         .expect("Songbird Voice client placed in at initialisation.")
@@ -179,132 +725,329 @@ async fn play_youtube_video_url(ctx: &Context, msg: &Message, query: String, is_
     if let Some(handler_lock) = manager.get(guild_id) {
         let mut handler = handler_lock.lock().await;
 
-        let source = match if is_url {
-            Restartable::ytdl(query, true).await
-        } else {
-            Restartable::ytdl_search(query, true).await
-        } {
-            Ok(source) => source,
-            Err(why) => {
-                println!("Err starting source: {:?}", why);
+        let (position, _) = enqueue(
+            &ctx.data,
+            &mut handler,
+            guild_id,
+            &query,
+            is_url,
+            invocation.author().name.to_owned(),
+            placement,
+        )
+        .await?;
+
+        Ok(position)
+    } else {
+        Err(Error::NotConnected)
+    }
+}
 
-                return Ok(());
-            }
-        };
+/// Loads `query` and queues it at `placement` on `handler` for `requester`,
+/// returning its position in the queue and its handle.
+async fn enqueue(
+    data: &Arc<RwLock<TypeMap>>,
+    handler: &mut Call,
+    guild_id: GuildId,
+    query: &str,
+    is_url: bool,
+    requester: String,
+    placement: Placement,
+) -> CaitlynResult<(usize, TrackHandle)> {
+    let (_, settings) = {
+        let data_read = data.read().await;
+        settings_from_data(&data_read, Some(guild_id)).await
+    };
+    let filters = guild_filters(data, guild_id).await;
+    let (resolver, audio_files) = {
+        let data_read = data.read().await;
+        let resolver = data_read
+            .get::<Resolver>()
+            .expect("Expected metadata resolver in TypeMap.")
+            .clone();
+        let audio_files = data_read
+            .get::<AudioFiles>()
+            .expect("Expected audio cache in TypeMap.")
+            .clone();
+        (resolver, audio_files)
+    };
+    let mut source = YtdlSource::new(query, is_url, filters.clone(), resolver).cached(audio_files);
+    if settings.normalize() {
+        let data_read = data.read().await;
+        let cache = data_read
+            .get::<Loudness>()
+            .expect("Expected loudness cache in TypeMap.")
+            .clone();
+        source = source.normalized(cache);
+    }
 
-        let track = handler.enqueue_source(source.into());
+    let (source, info) = match source.into_input().await {
+        Ok(input) => input,
+        Err(why) => {
+            warn!("Err starting source for {}: {:?}", query, why);
 
-        let queue_lock = {
-            let data_read = ctx.data.read().await;
-            data_read
-                .get::<Queue>()
-                .expect("Expected Queue in TypeMap.")
-                .clone()
-        };
-        {
-            let mut queue = queue_lock.write().await;
+            return Err(Error::Source(format!(
+                "Could not load `{}`: {}",
+                query, why
+            )));
+        }
+    };
 
-            let metadata = &track.metadata();
-
-            let title = match_else_none(&metadata.title);
-            let thumbnail = match_else_none(&metadata.thumbnail);
-            let artist = match_else_none(&metadata.artist);
-            let channel = match_else_none(&metadata.channel);
-            let date = match_else_none(&metadata.date);
-            let url = match_else_none(&metadata.source_url);
-            let duration = metadata.duration.to_owned();
-            let starttime = metadata.start_time.to_owned();
-
-            let track = Track {
-                requester: msg.author.name.to_owned(),
-                url,
-                title,
-                thumbnail,
-                artist,
-                channel,
-                date,
-                starttime,
-                duration,
-            };
-            queue.push(track);
+    let track = handler.enqueue_source(source.into());
+    let _ = track.set_volume(f32::from(settings.volume()) / 100.0);
+    if !info.chapters.is_empty() {
+        track
+            .typemap()
+            .write()
+            .await
+            .insert::<Chapters>(info.chapters);
+    }
+    let _ = track.add_event(
+        Event::Periodic(TRANSITION_TICK, None),
+        Transitions {
+            queue: handler.queue().clone(),
+            data: data.clone(),
+            filters: filters.clone(),
+            guild_id,
+        },
+    );
+    if settings.skip_segments() {
+        skip_segments(data, &track, filters).await;
+    }
+    let handle = track.clone();
+
+    let queue_lock = {
+        let data_read = data.read().await;
+        data_read
+            .get::<Queue>()
+            .expect("Expected Queue in TypeMap.")
+            .clone()
+    };
+    let mut queue = queue_lock.write().await;
+
+    let metadata = &track.metadata();
+
+    let title = match_else_none(&metadata.title);
+    let thumbnail = match_else_none(&metadata.thumbnail);
+    let artist = match_else_none(&metadata.artist);
+    let channel = match_else_none(&metadata.channel);
+    let date = match_else_none(&metadata.date);
+    let url = match_else_none(&metadata.source_url);
+    let duration = metadata.duration.to_owned();
+    let starttime = metadata.start_time.to_owned();
+
+    let track = Track {
+        requester,
+        url,
+        title,
+        thumbnail,
+        artist,
+        channel,
+        date,
+        starttime,
+        duration,
+        live: info.live,
+        handle_id: handle.uuid().as_u128(),
+    };
+    queue.push(track);
+    let mut position = queue.len() - 1;
+
+    // Songbird only appends, so move the new track forward in both
+    // queues to keep them in the same order.
+    let target = match placement {
+        Placement::Back => position,
+        Placement::Next => 1,
+        Placement::Now => 0,
+        Placement::At(index) => index,
+    };
+    if target < position {
+        let current = handler.queue().current();
+        handler.queue().modify_queue(|queued| {
+            if let Some(item) = queued.pop_back() {
+                queued.insert(target.min(queued.len()), item);
+            }
+        });
+        if let Some(entry) = queue.pop() {
+            queue.insert(target, entry);
         }
-    } else {
-        check_msg(
-            msg.channel_id
-                .say(&ctx.http, "Not in a voice channel to play in")
-                .await,
-        );
+        if target == 0 {
+            if let Some(current) = current {
+                let _ = current.pause();
+            }
+            let _ = handle.play();
+        }
+        position = target;
     }
-    Ok(())
+
+    Ok((position, handle))
 }
 
-async fn play_youtube_playlist(ctx: &Context, msg: &Message, url: String) -> CommandResult {
-    let regex =
-        Regex::new(r"(?:(?:PL|LL|EC|UU|FL|RD|UL|TL|PU|OLAK5uy_)[0-9A-Za-z-_]{10,}|RDMM)").unwrap();
-    let playlist_id = regex
-        .captures_iter(&url)
-        .next()
-        .unwrap()
-        .iter()
-        .next()
-        .unwrap()
-        .unwrap()
-        .as_str();
+async fn play_youtube_playlist(
+    ctx: &Context,
+    invocation: &Invocation<'_>,
+    url: String,
+) -> CaitlynResult {
+    let regex = Regex::new(r"(?:(?:PL|LL|EC|UU|FL|RD|UL|TL|PU|OLAK5uy_)[0-9A-Za-z-_]{10,}|RDMM)")
+        .expect("Playlist id regex is valid");
+    let playlist_id = match regex.find(&url) {
+        Some(found) => found.as_str(),
+        None => {
+            return Err(Error::BadArgument(String::from(
+                "That doesn't look like a Youtube playlist url.",
+            )))
+        }
+    };
 
-    let id = playlist_id.parse()?;
-    let ytextract = ytextract::Client::new();
+    let id = playlist_id
+        .parse()
+        .map_err(|_| Error::BadArgument(format!("`{}` is not a playlist id.", playlist_id)))?;
+    let resolver = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<Resolver>()
+            .expect("Expected metadata resolver in TypeMap.")
+            .clone()
+    };
 
-    let playlist = ytextract.playlist(id).await?;
+    let playlist = resolver
+        .client()
+        .playlist(id)
+        .await
+        .map_err(|why| Error::Source(format!("Could not load the playlist: {}", why)))?;
 
-    let videos = playlist.videos();
+    let (config, _) = guild_settings(ctx, invocation.guild_id()).await;
+
+    let videos = playlist.videos().take(config.limits.playlist_size);
     futures::pin_mut!(videos);
     let mut to_be_enqueued: Vec<String> = Vec::new();
 
     while let Some(item) = videos.next().await {
         match item {
             Ok(video) => to_be_enqueued.push(format!("https://youtu.be/{}", video.id())),
-            Err(err) => println!("{:#?},", err),
+            Err(err) => warn!("Skipping playlist entry: {:?}", err),
         }
     }
 
+    // Look the tracks up together first, rather than one by one as they
+    // are queued.
+    resolver.prefetch(&to_be_enqueued).await;
+    let (mut added, mut skipped) = (0, 0);
     for uri in to_be_enqueued {
-        play_youtube_video_url(&ctx, &msg, uri, true).await?;
+        match play_youtube_video_url(ctx, invocation, uri.clone(), true, Placement::Back).await {
+            Ok(_) => added += 1,
+            // Nothing else will queue either.
+            Err(Error::NotConnected) => return Err(Error::NotConnected),
+            Err(why) => {
+                warn!("Skipping unavailable playlist entry {}: {:?}", uri, why);
+                skipped += 1;
+            }
+        }
     }
 
-    check_msg(
-        msg.channel_id
-            .say(&ctx.http, "Added playlist to queue.")
-            .await,
-    );
+    let reply = if skipped == 0 {
+        format!("Added {} tracks.", added)
+    } else {
+        format!("Added {} tracks (skipped {} unavailable).", added, skipped)
+    };
+    invocation.say(ctx, reply).await?;
     Ok(())
 }
 
 #[command]
 #[only_in(guilds)]
+#[description = "Play a Youtube video or playlist by url, or the first search result."]
+#[usage = "<url or search>"]
+#[example = "https://youtu.be/dQw4w9WgXcQ"]
+#[example = "never gonna give you up"]
+#[bucket = "play"]
 async fn play(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
-    let query = String::from(args.message());
+    Ok(_play(ctx, &Invocation::Message(msg), String::from(args.message())).await?)
+}
+
+#[command]
+#[only_in(guilds)]
+#[aliases(pn)]
+#[description = "Play a Youtube video or search result right after the current track."]
+#[usage = "<url or search>"]
+#[example = "never gonna give you up"]
+#[bucket = "play"]
+async fn playnext(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    Ok(_play_at(
+        ctx,
+        &Invocation::Message(msg),
+        String::from(args.message()),
+        Placement::Next,
+    )
+    .await?)
+}
+
+#[command]
+#[only_in(guilds)]
+#[description = "Interrupt the current track with a Youtube video or search result. The interrupted track plays next."]
+#[usage = "<url or search>"]
+#[example = "never gonna give you up"]
+#[bucket = "play"]
+async fn playnow(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    Ok(_play_at(
+        ctx,
+        &Invocation::Message(msg),
+        String::from(args.message()),
+        Placement::Now,
+    )
+    .await?)
+}
+
+pub async fn _play(ctx: &Context, invocation: &Invocation<'_>, query: String) -> CaitlynResult {
+    _play_at(ctx, invocation, query, Placement::Back).await
+}
+
+pub async fn _play_at(
+    ctx: &Context,
+    invocation: &Invocation<'_>,
+    query: String,
+    placement: Placement,
+) -> CaitlynResult {
+    let guild_id = match invocation.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
 
     let manager = songbird::get(ctx)
         .await
         .expect("Songbird Voice client placed in at initialisation.")
         .clone();
 
-    if manager.get(msg.guild_id.unwrap()).is_none() {
-        let result = _join(&ctx, &msg, false).await;
-        if !result {
-            return Ok(())
+    if query.trim().is_empty() {
+        return Err(Error::BadArgument(String::from(
+            "Give me a Youtube url or something to search for.",
+        )));
+    }
+
+    let is_playlist = query.contains("playlist?list");
+    if placement != Placement::Back {
+        check_dj(ctx, invocation).await?;
+        if is_playlist {
+            return Err(Error::BadArgument(String::from(
+                "Only single videos can skip the queue, use `play` for playlists.",
+            )));
         }
     }
 
-    return if query.contains("youtube") || query.contains("youtu.be") {
-        if !query.contains("playlist?list") {
-            play_youtube_video_url(&ctx, &msg, query, true).await
+    if manager.get(guild_id).is_none() {
+        _join(ctx, invocation, false).await?;
+    }
+
+    let result = if query.contains("youtube") || query.contains("youtu.be") {
+        if !is_playlist {
+            play_youtube_video(ctx, invocation, query, true, placement).await
         } else {
-            play_youtube_playlist(&ctx, &msg, query).await
+            play_youtube_playlist(ctx, invocation, query).await
         }
     } else {
-        play_youtube_video_url(&ctx, &msg, query, false).await
-    }
+        play_youtube_video(ctx, invocation, query, false, placement).await
+    };
+    update_player(&ctx.http, &ctx.data, guild_id).await;
+
+    result
 }
 fn match_else_none(input: &Option<String>) -> String {
     match input {
@@ -315,43 +1058,172 @@ fn match_else_none(input: &Option<String>) -> String {
 
 #[command]
 #[only_in(guilds)]
+#[description = "Skip the current track."]
+#[bucket = "control"]
 async fn skip(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
-    let guild = msg.guild(&ctx.cache).unwrap();
-    let guild_id = guild.id;
+    Ok(_skip(ctx, &Invocation::Message(msg)).await?)
+}
+
+pub async fn _skip(ctx: &Context, invocation: &Invocation<'_>) -> CaitlynResult {
+    let guild_id = match invocation.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
 
+    check_dj(ctx, invocation).await?;
+    let left = skip_track(ctx, guild_id).await?;
+    invocation
+        .say(ctx, format!("Song skipped: {} in queue.", left))
+        .await?;
+
+    Ok(())
+}
+
+/// Skips the current track, returning how many are left in the queue.
+pub async fn skip_track(ctx: &Context, guild_id: GuildId) -> CaitlynResult<usize> {
     let manager = songbird::get(ctx)
         .await
         .expect("Songbird Voice client placed in at initialisation.")
         .clone();
 
-    if let Some(handler_lock) = manager.get(guild_id) {
-        let handler = handler_lock.lock().await;
-        let queue = handler.queue();
-        let _ = queue.skip();
-
-        check_msg(
-            msg.channel_id
-                .say(
-                    &ctx.http,
-                    format!("Song skipped: {} in queue.", queue.len()),
-                )
-                .await,
-        );
-    } else {
-        check_msg(
-            msg.channel_id
-                .say(&ctx.http, "Not in a voice channel to play in")
-                .await,
-        );
-    }
+    match manager.get(guild_id) {
+        Some(handler_lock) => {
+            let handler = handler_lock.lock().await;
+            let queue = handler.queue();
+            let _ = queue.skip();
 
-    Ok(())
+            Ok(queue.len())
+        }
+        None => Err(Error::NotConnected),
+    }
 }
 
 #[command]
 #[only_in(guilds)]
 #[aliases(np, song)]
+#[description = "Show the track that is currently playing."]
+#[bucket = "embeds"]
 async fn playing(ctx: &Context, msg: &Message) -> CommandResult {
+    Ok(_playing(ctx, &Invocation::Message(msg)).await?)
+}
+
+/// Ytdl dates look like 20220131, escaped so markdown keeps the separators.
+fn format_upload_date(date: &str) -> String {
+    if date.len() == 8 && date.chars().all(|c| c.is_ascii_digit()) {
+        format!("{}\\\\{}\\\\{}", &date[..4], &date[4..6], &date[6..])
+    } else {
+        date.to_string()
+    }
+}
+
+fn now_playing_embed(
+    config: &Config,
+    settings: &GuildSettings,
+    track: &Track,
+    state: Option<PlaybackState>,
+    filters: &AudioFilters,
+    details: &PlayingDetails,
+) -> CreateEmbed {
+    let date = format_upload_date(&track.date);
+
+    let mut embed = CreateEmbed::default();
+    embed
+        .colour(settings.colour(config))
+        .author(|a| {
+            a.name(format!("Now playing: {}", &track.title))
+                .url(&track.url)
+                .icon_url(&config.icon_url)
+        })
+        .field("Requested By:", &track.requester, true)
+        .thumbnail(&track.thumbnail)
+        .field("Uploaded By:", &track.channel, true)
+        .field("Upload Date:", &date, true);
+    if let Some(state) = state {
+        embed
+            .field(
+                "Status:",
+                if state.paused { "Paused" } else { "Playing" },
+                true,
+            )
+            .field("Loop:", if state.looping { "On" } else { "Off" }, true)
+            .field("Volume:", format!("{}%", state.volume), true);
+    }
+    if !filters.is_empty() {
+        embed.field("Filters:", filters.describe(), false);
+    }
+    if let Some((index, chapter)) = &details.chapter {
+        embed.field(
+            "Chapter:",
+            format!(
+                "{}/{}: {} ({})",
+                index + 1,
+                details.chapters,
+                chapter.name(*index),
+                format_duration(chapter.start)
+            ),
+            false,
+        );
+    }
+    if let Some(skipped) = describe_skipped(&details.skipped) {
+        embed.field("Skipped:", skipped, false);
+    }
+
+    embed
+}
+
+/// What `playing` shows about the track besides its metadata: the chapter
+/// it is in and the segments it skipped.
+#[derive(Default)]
+struct PlayingDetails {
+    chapter: Option<(usize, Chapter)>,
+    chapters: usize,
+    skipped: Vec<Segment>,
+}
+
+async fn playing_details(track: &TrackHandle, speed: f64) -> PlayingDetails {
+    let position = match track.get_info().await {
+        Ok(info) => info.position.mul_f64(speed),
+        Err(_) => Duration::ZERO,
+    };
+    let typemap = track.typemap().read().await;
+
+    let chapters = typemap
+        .get::<Chapters>()
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    PlayingDetails {
+        chapter: current_chapter(chapters, position).map(|index| (index, chapters[index].clone())),
+        chapters: chapters.len(),
+        skipped: typemap
+            .get::<SkippedSegments>()
+            .cloned()
+            .unwrap_or_default(),
+    }
+}
+
+/// Tells a `playing` message that its track ended, so it can disable its
+/// controls.
+struct TrackEnded(std::sync::Mutex<Option<oneshot::Sender<()>>>);
+
+#[async_trait]
+impl VoiceEventHandler for TrackEnded {
+    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        if let Some(sender) = self.0.lock().ok().and_then(|mut sender| sender.take()) {
+            let _ = sender.send(());
+        }
+
+        Some(Event::Cancel)
+    }
+}
+
+pub async fn _playing(ctx: &Context, invocation: &Invocation<'_>) -> CaitlynResult {
+    let (config, settings) = guild_settings(ctx, invocation.guild_id()).await;
+
+    let guild_id = match invocation.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
     let queue_lock = {
         let data_read = ctx.data.read().await;
 
@@ -361,156 +1233,442 @@ async fn playing(ctx: &Context, msg: &Message) -> CommandResult {
             .clone()
     };
 
-    let playlist = queue_lock.read().await;
+    let current = queue_lock.read().await.first().cloned();
+    let current = match current {
+        Some(current) => current,
+        None => {
+            invocation.say(ctx, "Nothing is currently playing.").await?;
+            return Ok(());
+        }
+    };
 
-    if let Some(current_track) = playlist.iter().next() {
-        let mut date = current_track.date.to_owned();
-        date.insert(4, '\\');
-        date.insert(4, '\\');
-        date.insert(8, '\\');
-        date.insert(8, '\\');
-
-        check_msg(
-            msg.channel_id
-                .send_message(&ctx.http, |m| {
-                    m.embed(|e| {
-                        e.author(|a| {
-                            a.name(format!("Now playing: {}", &current_track.title))
-                                .url(&current_track.url)
-                                .icon_url("https://i.imgur.com/vVvNHcj.png")
-                        })
-                        .field("Requested By:", &current_track.requester, true)
-                        .thumbnail(&current_track.thumbnail)
-                        .field("Uploaded By:", &current_track.channel, true)
-                        .field("Upload Date:", &date, true)
-                    })
+    let filters = guild_filters(&ctx.data, guild_id)
+        .await
+        .read()
+        .await
+        .clone();
+
+    let track = match current_track(ctx, guild_id).await {
+        Ok(track) => track,
+        Err(_) => {
+            invocation
+                .send_embed(
+                    ctx,
+                    now_playing_embed(
+                        &config,
+                        &settings,
+                        &current,
+                        None,
+                        &filters,
+                        &PlayingDetails::default(),
+                    ),
+                )
+                .await?;
+            return Ok(());
+        }
+    };
+    let mut state = playback_state(&track).await.unwrap_or(PlaybackState {
+        paused: false,
+        looping: false,
+        volume: settings.volume(),
+    });
+
+    let mut details = playing_details(&track, filters.speed_factor()).await;
+
+    let mut buttons = CreateComponents::default();
+    control_buttons(&mut buttons, state, false);
+    let mut message = invocation
+        .send(
+            ctx,
+            now_playing_embed(
+                &config,
+                &settings,
+                &current,
+                Some(state),
+                &filters,
+                &details,
+            ),
+            Some(buttons),
+        )
+        .await?;
+
+    let (ended_sender, mut ended) = oneshot::channel();
+    let _ = track.add_event(
+        Event::Track(TrackEvent::End),
+        TrackEnded(std::sync::Mutex::new(Some(ended_sender))),
+    );
+
+    let remaining = match (current.duration, track.get_info().await) {
+        (Some(duration), Ok(info)) => duration.saturating_sub(info.position),
+        _ => Duration::ZERO,
+    };
+    let mut interactions = message
+        .await_component_interactions(&ctx)
+        .timeout(remaining.max(settings.queue_timeout(&config)))
+        .build();
+
+    loop {
+        let interaction = tokio::select! {
+            interaction = interactions.next() => match interaction {
+                Some(interaction) => interaction,
+                None => break,
+            },
+            _ = &mut ended => break,
+        };
+
+        let component = Invocation::Component(&interaction);
+        let id = interaction.data.custom_id.as_str();
+        let result = match check_dj(ctx, &component).await {
+            Err(why) => Err(why),
+            Ok(()) => match id {
+                "np_pause" => set_paused(ctx, guild_id, !state.paused).await,
+                "np_skip" => skip_track(ctx, guild_id).await.map(|_| ()),
+                "np_stop" => stop_playback(ctx, guild_id).await,
+                "np_loop" => toggle_loop(ctx, guild_id).await.map(|_| ()),
+                "np_shuffle" => shuffle_queue(ctx, guild_id).await.map(|_| ()),
+                "np_voldown" => {
+                    set_volume(ctx, guild_id, state.volume.saturating_sub(VOLUME_STEP)).await
+                }
+                "np_volup" => {
+                    set_volume(ctx, guild_id, state.volume.saturating_add(VOLUME_STEP)).await
+                }
+                _ => Ok(()),
+            },
+        };
+
+        if let Err(why) = result {
+            let _ = interaction
+                .create_interaction_response(&ctx.http, |r| {
+                    r.kind(InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|d| d.content(why.to_string()).ephemeral(true))
                 })
-                .await,
-        );
-    } else {
-        check_msg(
-            msg.channel_id
-                .say(&ctx.http, "Nothing is currently playing.")
-                .await,
-        );
+                .await;
+            continue;
+        }
+
+        let finished = matches!(id, "np_skip" | "np_stop");
+        if !finished {
+            state = playback_state(&track).await.unwrap_or(state);
+            details = playing_details(&track, filters.speed_factor()).await;
+        }
+        let _ = interaction
+            .create_interaction_response(&ctx.http, |r| {
+                r.kind(InteractionResponseType::UpdateMessage)
+                    .interaction_response_data(|d| {
+                        d.add_embed(now_playing_embed(
+                            &config,
+                            &settings,
+                            &current,
+                            Some(state),
+                            &filters,
+                            &details,
+                        ))
+                        .components(|c| control_buttons(c, state, finished))
+                    })
+            })
+            .await;
+        if finished {
+            return Ok(());
+        }
     }
+
+    let _ = message
+        .edit(&ctx.http, |m| {
+            m.components(|c| control_buttons(c, state, true))
+        })
+        .await;
+
     Ok(())
 }
 
 #[command]
 #[only_in(guilds)]
+#[description = "Join the voice channel you are in."]
+#[bucket = "control"]
 async fn join(ctx: &Context, msg: &Message) -> CommandResult {
-    _join(&ctx, &msg, true).await;
-    Ok(())
+    Ok(_join(ctx, &Invocation::Message(msg), true).await?)
 }
 
-async fn _join(ctx: &Context, msg: &Message, invoked_by_command: bool) -> bool {
-    let guild = msg.guild(&ctx.cache).unwrap();
+pub async fn _join(
+    ctx: &Context,
+    invocation: &Invocation<'_>,
+    invoked_by_command: bool,
+) -> CaitlynResult {
+    let guild = match invocation
+        .guild_id()
+        .and_then(|guild_id| guild_id.to_guild_cached(&ctx.cache))
+    {
+        Some(guild) => guild,
+        None => return Err(Error::NotInVoice),
+    };
     let guild_id = guild.id;
 
     let channel_id = guild
         .voice_states
-        .get(&msg.author.id)
+        .get(&invocation.author().id)
         .and_then(|voice_state| voice_state.channel_id);
 
     let connect_to = match channel_id {
         Some(channel) => channel,
-        None => {
-            check_msg(msg.reply(ctx, "Not in a voice channel").await);
-
-            return false;
-        }
+        None => return Err(Error::NotInVoice),
     };
 
+    let (_, settings) = guild_settings(ctx, Some(guild_id)).await;
+    if !settings.music_voice_channel_allowed(connect_to) {
+        return Err(Error::WrongChannel(format!(
+            "I can only play music in {}.",
+            settings.music_voice_channel_mentions()
+        )));
+    }
+
     let manager = songbird::get(ctx)
         .await
         .expect("Songbird Voice client placed in at initialisation.")
         .clone();
 
+    // The Call and its handlers outlive the connection, so they are only set
+    // up the first time.
+    let first_join = manager.get(guild_id).is_none();
     let (handle_lock, success) = manager.join(guild_id, connect_to).await;
 
     if let Ok(_channel) = success {
         if invoked_by_command {
-            check_msg(
-                msg.channel_id
-                    .say(&ctx.http, &format!("Joined {}", connect_to.mention()))
-                    .await,
-            );
+            invocation
+                .say(ctx, format!("Joined {}", connect_to.mention()))
+                .await?;
         }
 
-        let chan_id = msg.channel_id;
+        let chan_id = invocation.channel_id();
 
         let send_http = ctx.http.clone();
 
-        let send_guild = msg.guild_id.unwrap().clone();
+        let send_guild = guild_id;
 
         let send_data = ctx.data.clone();
 
+        if !first_join {
+            return Ok(());
+        }
         let mut handle = handle_lock.lock().await;
 
+        handle.add_global_event(
+            Event::Track(TrackEvent::Play),
+            TrackStartNotifier {
+                http: send_http.clone(),
+                data: send_data.clone(),
+                guild_id: send_guild,
+            },
+        );
         handle.add_global_event(
             Event::Track(TrackEvent::End),
             TrackEndNotifier {
                 chan_id,
-                http: send_http,
-                data: send_data,
+                http: send_http.clone(),
+                data: send_data.clone(),
                 guild_id: send_guild,
             },
         );
-    } else {
-        check_msg(
-            msg.channel_id
-                .say(&ctx.http, "Error joining the channel")
-                .await,
+        handle.add_global_event(
+            Event::Track(TrackEvent::Error),
+            TrackErrorNotifier {
+                chan_id,
+                http: send_http.clone(),
+                data: send_data.clone(),
+                manager: manager.clone(),
+                guild_id: send_guild,
+            },
         );
-        return false;
-    }
 
-    true
-}
-fn check_msg(result: SerenityResult<Message>) {
-    if let Err(why) = result {
-        println!("Error sending message: {:?}", why);
+        let watcher = VoiceWatcher {
+            chan_id,
+            voice_channel: connect_to,
+            http: send_http,
+            data: send_data,
+            manager: manager.clone(),
+            guild_id: send_guild,
+            reconnecting: Arc::default(),
+        };
+        for event in [
+            CoreEvent::DriverConnect,
+            CoreEvent::DriverReconnect,
+            CoreEvent::DriverDisconnect,
+        ] {
+            handle.add_global_event(Event::Core(event), watcher.clone());
+        }
+    } else if let Err(why) = success {
+        warn!("Failed to join {}: {:?}", connect_to, why);
+        // Start over with a new Call, and its handlers, next time.
+        if first_join {
+            let _ = manager.remove(guild_id).await;
+        }
+        return Err(Error::Voice(format!(
+            "I couldn't join {}, check that I'm allowed to connect there.",
+            connect_to.mention()
+        )));
     }
-}
 
+    Ok(())
+}
 
 #[command]
 #[only_in(guilds)]
+#[description = "Stop playing and clear the queue."]
+#[bucket = "control"]
 async fn stop(ctx: &Context, msg: &Message, _args: Args) -> CommandResult {
-    let guild = msg.guild(&ctx.cache).unwrap();
-    let guild_id = guild.id;
+    Ok(_stop(ctx, &Invocation::Message(msg)).await?)
+}
+
+pub async fn _stop(ctx: &Context, invocation: &Invocation<'_>) -> CaitlynResult {
+    let guild_id = match invocation.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
 
+    check_dj(ctx, invocation).await?;
+    stop_playback(ctx, guild_id).await?;
+    invocation.say(ctx, "Queue cleared.").await?;
+
+    Ok(())
+}
+
+/// Stops the current track and clears the queue.
+pub async fn stop_playback(ctx: &Context, guild_id: GuildId) -> CaitlynResult {
     let manager = songbird::get(ctx)
         .await
         .expect("Songbird Voice client placed in at initialisation.")
         .clone();
 
-    if let Some(handler_lock) = manager.get(guild_id) {
-        let handler = handler_lock.lock().await;
-        let queue = handler.queue();
-        let _ = queue.stop();
+    match manager.get(guild_id) {
+        Some(handler_lock) => {
+            let handler = handler_lock.lock().await;
+            let queue = handler.queue();
+            let _ = queue.stop();
+        }
+        None => return Err(Error::NotConnected),
+    }
 
+    let queue_lock = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<Queue>()
+            .expect("Expected Queue in TypeMap.")
+            .clone()
+    };
+    {
+        let mut queue = queue_lock.write().await;
+        queue.clear();
+    }
+    update_player(&ctx.http, &ctx.data, guild_id).await;
+
+    Ok(())
+}
+
+fn track_lyrics_query(track: &Track, title_rules: &TitleRules) -> LyricsQuery {
+    let normalized = title_rules.normalize(&track.title);
+
+    let artist = match normalized.artist {
+        Some(artist) => Some(artist),
+        None if track.artist != "None" => Some(track.artist.to_owned()),
+        None => track
+            .channel
+            .strip_suffix(" - Topic")
+            .map(|artist| artist.to_string()),
+    };
+
+    LyricsQuery {
+        artist,
+        title: normalized.title,
+    }
+}
+
+#[command]
+#[only_in(guilds)]
+#[description = "Show lyrics for the current track, or for a search."]
+#[usage = "[artist - title]"]
+#[example = "Rick Astley - Never Gonna Give You Up"]
+#[bucket = "embeds"]
+async fn lyrics(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let query = if args.is_empty() {
+        None
+    } else {
+        Some(String::from(args.message()))
+    };
+
+    Ok(_lyrics(ctx, &Invocation::Message(msg), query).await?)
+}
+
+pub async fn _lyrics(
+    ctx: &Context,
+    invocation: &Invocation<'_>,
+    query: Option<String>,
+) -> CaitlynResult {
+    let query = if let Some(query) = query {
+        LyricsQuery::parse(&query)
+    } else {
         let queue_lock = {
             let data_read = ctx.data.read().await;
+
             data_read
                 .get::<Queue>()
-                .expect("Expected Queue in TypeMap.")
+                .expect("Expected queue in TypeMap.")
                 .clone()
         };
-        {
-            let mut queue = queue_lock.write().await;
-            queue.clear();
+        let title_rules = {
+            let data_read = ctx.data.read().await;
+
+            data_read
+                .get::<Titles>()
+                .expect("Expected title rules in TypeMap.")
+                .clone()
+        };
+        let playlist = queue_lock.read().await;
+
+        match playlist.first() {
+            Some(track) => track_lyrics_query(track, &title_rules),
+            None => {
+                invocation.say(ctx, "Nothing is currently playing.").await?;
+                return Ok(());
+            }
         }
-        check_msg(msg.channel_id.say(&ctx.http, "Queue cleared.").await);
-    } else {
-        check_msg(
-            msg.channel_id
-                .say(&ctx.http, "Not in a voice channel to play in")
-                .await,
-        );
+    };
+
+    let provider = {
+        let data_read = ctx.data.read().await;
+
+        data_read
+            .get::<Lyrics>()
+            .expect("Expected lyrics provider in TypeMap.")
+            .clone()
+    };
+
+    let lyrics = match provider.lyrics(&query).await {
+        Ok(Some(lyrics)) => lyrics,
+        Ok(None) => {
+            invocation
+                .say(ctx, format!("No lyrics found for {}.", query.display()))
+                .await?;
+            return Ok(());
+        }
+        Err(why) => {
+            warn!("Failed to fetch lyrics for {}: {:?}", query.display(), why);
+            invocation.say(ctx, "Failed to fetch lyrics.").await?;
+            return Ok(());
+        }
+    };
+
+    let (config, settings) = guild_settings(ctx, invocation.guild_id()).await;
+
+    let pages = split_pages(&lyrics, EMBED_DESCRIPTION_LIMIT);
+    for (i, page) in enumerate(pages.iter()) {
+        let mut embed = CreateEmbed::default();
+        embed
+            .colour(settings.colour(&config))
+            .title(format!("Lyrics: {}", query.display()))
+            .description(page)
+            .footer(|f| f.text(format!("Page: {}/{}", i + 1, pages.len())));
+
+        invocation.send_embed(ctx, embed).await?;
     }
 
     Ok(())
 }
-
@@ -0,0 +1,323 @@
+use crate::commands::controls::check_dj;
+use crate::commands::invocation::Invocation;
+use crate::commands::music::{_play, format_length, skip_track, stop_playback};
+use crate::config::Config;
+use crate::error::{self, CaitlynResult, Error};
+use crate::pagination::truncate;
+use crate::ratelimit::check_bucket;
+use crate::storage::{guild_settings, settings_from_data, GuildSettings};
+use crate::{Guilds, Queue, Track};
+use serenity::builder::{CreateComponents, CreateEmbed};
+use serenity::framework::standard::macros::command;
+use serenity::framework::standard::CommandResult;
+use serenity::http::Http;
+use serenity::model::application::component::ButtonStyle;
+use serenity::model::application::interaction::message_component::MessageComponentInteraction;
+use serenity::model::application::interaction::InteractionResponseType;
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+use std::time::Duration;
+
+/// Custom ids of the player buttons start with this, so `interaction_create`
+/// can tell them apart from the queue buttons.
+pub const PLAYER_BUTTON_PREFIX: &str = "player_";
+
+pub const REQUEST_CHANNEL_NAME: &str = "song-requests";
+
+/// How many upcoming tracks the player message lists.
+const PLAYER_QUEUE_PREVIEW: usize = 5;
+
+/// How long replies in the request channel stay before they are cleaned up.
+const REQUEST_REPLY_LIFETIME: Duration = Duration::from_secs(10);
+
+fn player_embed(config: &Config, settings: &GuildSettings, playlist: &[Track]) -> CreateEmbed {
+    let mut embed = CreateEmbed::default();
+    embed
+        .colour(settings.colour(config))
+        .footer(|f| f.text("Send a song name or a Youtube link in this channel to play it."));
+
+    let current = match playlist.first() {
+        Some(current) => current,
+        None => {
+            embed
+                .title("Nothing playing")
+                .description("The queue is empty.")
+                .image(&config.icon_url);
+            return embed;
+        }
+    };
+
+    embed
+        .author(|a| a.name("Now playing").icon_url(&config.icon_url))
+        .title(truncate(&current.title, 256))
+        .url(&current.url)
+        .field("Requested By:", &current.requester, true)
+        .field("Length:", format_length(current), true)
+        .field("Uploaded By:", &current.channel, true);
+    if current.thumbnail != "None" {
+        embed.image(&current.thumbnail);
+    }
+
+    let upcoming = &playlist[1..];
+    if !upcoming.is_empty() {
+        let mut lines: Vec<String> = upcoming
+            .iter()
+            .take(PLAYER_QUEUE_PREVIEW)
+            .enumerate()
+            .map(|(i, track)| {
+                format!(
+                    "`{}.` {} ({})",
+                    i + 1,
+                    truncate(&track.title, 80),
+                    format_length(track)
+                )
+            })
+            .collect();
+        if upcoming.len() > PLAYER_QUEUE_PREVIEW {
+            lines.push(format!(
+                "and {} more",
+                upcoming.len() - PLAYER_QUEUE_PREVIEW
+            ));
+        }
+        embed.field("Up Next:", lines.join("\n"), false);
+    }
+
+    embed
+}
+
+fn player_buttons(c: &mut CreateComponents, playing: bool) -> &mut CreateComponents {
+    c.create_action_row(|r| {
+        r.create_button(|b| {
+            b.custom_id("player_skip")
+                .label("Skip")
+                .style(ButtonStyle::Primary)
+                .disabled(!playing)
+        })
+        .create_button(|b| {
+            b.custom_id("player_stop")
+                .label("Stop")
+                .style(ButtonStyle::Danger)
+                .disabled(!playing)
+        })
+    })
+}
+
+/// Redraws the player message of `guild_id`, if it has a request channel.
+pub async fn update_player(http: &Http, data: &RwLock<TypeMap>, guild_id: GuildId) {
+    let (config, settings, queue_lock) = {
+        let data_read = data.read().await;
+        let (config, settings) = settings_from_data(&data_read, Some(guild_id)).await;
+        let queue_lock = data_read
+            .get::<Queue>()
+            .expect("Expected queue in TypeMap.")
+            .clone();
+
+        (config, settings, queue_lock)
+    };
+
+    let (channel_id, message_id) = match (settings.request_channel, settings.player_message) {
+        (Some(channel_id), Some(message_id)) => (ChannelId(channel_id), MessageId(message_id)),
+        _ => return,
+    };
+
+    let (embed, playing) = {
+        let playlist = queue_lock.read().await;
+        (
+            player_embed(&config, &settings, &playlist),
+            !playlist.is_empty(),
+        )
+    };
+
+    if let Err(why) = channel_id
+        .edit_message(http, message_id, |m| {
+            m.set_embed(embed)
+                .components(|c| player_buttons(c, playing))
+        })
+        .await
+    {
+        warn!("Failed to update the player in {}: {:?}", guild_id, why);
+    }
+}
+
+#[command]
+#[only_in(guilds)]
+#[required_permissions(MANAGE_GUILD)]
+#[description = "Create a song request channel with a player, or repost its player."]
+async fn setup(ctx: &Context, msg: &Message) -> CommandResult {
+    Ok(_setup(ctx, &Invocation::Message(msg)).await?)
+}
+
+pub async fn _setup(ctx: &Context, invocation: &Invocation<'_>) -> CaitlynResult {
+    let guild_id = match invocation.guild_id() {
+        Some(guild_id) => guild_id,
+        None => return Err(Error::GuildOnly),
+    };
+
+    // Slash command permissions can be changed by the server, so check here
+    // too.
+    if !invocation.has_permissions(ctx, Permissions::MANAGE_GUILD) {
+        return Err(Error::PermissionDenied(String::from(
+            "You need the Manage Server permission to set up the request channel.",
+        )));
+    }
+
+    let (_, settings) = guild_settings(ctx, Some(guild_id)).await;
+
+    let existing = settings
+        .request_channel
+        .map(ChannelId)
+        .filter(|channel_id| ctx.cache.guild_channel(*channel_id).is_some());
+    let channel_id = match existing {
+        Some(channel_id) => channel_id,
+        None => {
+            guild_id
+                .create_channel(&ctx.http, |c| {
+                    c.name(REQUEST_CHANNEL_NAME)
+                        .kind(ChannelType::Text)
+                        .topic("Send a song name or a Youtube link to play it.")
+                })
+                .await
+                .map_err(|why| {
+                    warn!("Failed to create the request channel: {:?}", why);
+                    Error::PermissionDenied(String::from(
+                        "I couldn't create the channel, check that I have the Manage Channels permission.",
+                    ))
+                })?
+                .id
+        }
+    };
+
+    let message = channel_id
+        .send_message(&ctx.http, |m| m.content("Loading the player…"))
+        .await?;
+    if let (Some(_), Some(old)) = (existing, settings.player_message) {
+        let _ = channel_id.delete_message(&ctx.http, old).await;
+    }
+
+    let store = {
+        let data_read = ctx.data.read().await;
+
+        data_read
+            .get::<Guilds>()
+            .expect("Expected guild settings in TypeMap.")
+            .clone()
+    };
+    store
+        .write()
+        .await
+        .update(guild_id, |settings| {
+            settings.request_channel = Some(channel_id.0);
+            settings.player_message = Some(message.id.0);
+            Ok(())
+        })
+        .map_err(|why| Error::BadArgument(format!("Could not set up the channel: {}.", why)))?;
+
+    update_player(&ctx.http, &ctx.data, guild_id).await;
+
+    invocation
+        .say(
+            ctx,
+            format!("Song requests go in {} now.", channel_id.mention()),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Deletes a message in the request channel after a while, unless it has
+/// become the player in the meantime.
+fn delete_later(ctx: &Context, msg: &Message) {
+    let ctx = ctx.clone();
+    let (guild_id, channel_id, message_id) = (msg.guild_id, msg.channel_id, msg.id);
+
+    tokio::spawn(async move {
+        tokio::time::sleep(REQUEST_REPLY_LIFETIME).await;
+        let (_, settings) = guild_settings(&ctx, guild_id).await;
+        if settings.player_message != Some(message_id.0) {
+            let _ = channel_id.delete_message(&ctx.http, message_id).await;
+        }
+    });
+}
+
+/// Handles a message in the request channel: plain messages are played and
+/// deleted, commands and replies are cleaned up after a while so only the
+/// player stays. Returns whether `msg` was in a request channel.
+pub async fn handle_request(ctx: &Context, msg: &Message) -> bool {
+    let (config, settings) = guild_settings(ctx, msg.guild_id).await;
+
+    if settings.request_channel != Some(msg.channel_id.0) {
+        return false;
+    }
+
+    let bot_id = ctx.cache.current_user_id();
+    if msg.author.id == bot_id {
+        delete_later(ctx, msg);
+        return true;
+    }
+    if msg.author.bot {
+        return true;
+    }
+
+    let content = msg.content.trim();
+    let is_command = content.starts_with(settings.prefix(&config))
+        || content.starts_with(&format!("<@{}>", bot_id))
+        || content.starts_with(&format!("<@!{}>", bot_id));
+    if is_command {
+        delete_later(ctx, msg);
+        return true;
+    }
+
+    if !content.is_empty() {
+        let invocation = Invocation::Message(msg);
+        let result = match check_bucket(ctx, &invocation, "play").await {
+            Ok(()) => _play(ctx, &invocation, content.to_string()).await,
+            Err(why) => Err(why),
+        };
+        if let Err(why) = result {
+            error::report(ctx, &invocation, "play", &why.into()).await;
+        }
+    }
+    if let Err(why) = msg.delete(ctx).await {
+        warn!("Failed to delete a song request: {:?}", why);
+    }
+
+    true
+}
+
+/// Runs the player button in `interaction`.
+pub async fn handle_button(ctx: &Context, interaction: MessageComponentInteraction) {
+    let guild_id = match interaction.guild_id {
+        Some(guild_id) => guild_id,
+        None => return,
+    };
+
+    let result = match check_dj(ctx, &Invocation::Component(&interaction)).await {
+        Err(why) => Err(why),
+        Ok(()) => match interaction.data.custom_id.as_str() {
+            "player_skip" => skip_track(ctx, guild_id).await.map(|_| ()),
+            "player_stop" => stop_playback(ctx, guild_id).await,
+            _ => Ok(()),
+        },
+    };
+
+    let response = match result {
+        Ok(()) => {
+            interaction
+                .create_interaction_response(&ctx.http, |r| {
+                    r.kind(InteractionResponseType::DeferredUpdateMessage)
+                })
+                .await
+        }
+        Err(why) => {
+            interaction
+                .create_interaction_response(&ctx.http, |r| {
+                    r.kind(InteractionResponseType::ChannelMessageWithSource)
+                        .interaction_response_data(|d| d.content(why.to_string()).ephemeral(true))
+                })
+                .await
+        }
+    };
+    if let Err(why) = response {
+        warn!("Failed to answer a player button: {:?}", why);
+    }
+}
@@ -0,0 +1,491 @@
+use crate::commands::chapters::{_chapter, _chapters, ChapterTarget};
+use crate::commands::controls::{_loop, _pause, _shuffle, _volume, MAX_VOLUME};
+use crate::commands::filters::{_eq, _filter, _pitch, _speed};
+use crate::commands::general::{
+    _cache, _config, _help, _ping, _prefix, _userinfo, CacheAction, ConfigAction, PrefixAction,
+};
+use crate::commands::invocation::Invocation;
+use crate::commands::music::{
+    _join, _lyrics, _play, _play_at, _playing, _queue, _skip, _stop, check_music_channel, Placement,
+};
+use crate::commands::player::_setup;
+use crate::error::{self, CaitlynResult, Error};
+use crate::filters::{EQ_PRESETS, FILTER_PRESETS, MAX_SPEED, MIN_SPEED};
+use crate::pagination::truncate;
+use crate::ratelimit::check_bucket;
+use crate::storage::SETTING_KEYS;
+use crate::suggest::find_command;
+use crate::{GROUPS, MUSIC_GROUP};
+use serenity::client::Context;
+use serenity::model::application::command::{Command, CommandOptionType};
+use serenity::model::application::interaction::application_command::{
+    ApplicationCommandInteraction, CommandDataOption, CommandDataOptionValue,
+};
+use serenity::model::application::interaction::autocomplete::AutocompleteInteraction;
+use serenity::model::permissions::Permissions;
+use serenity::model::user::User;
+use serenity::Result as SerenityResult;
+
+const AUTOCOMPLETE_LIMIT: usize = 25;
+const CHOICE_LIMIT: usize = 100;
+
+pub async fn register(ctx: &Context) -> SerenityResult<Vec<Command>> {
+    Command::set_global_application_commands(&ctx.http, |commands| {
+        commands
+            .create_application_command(|c| {
+                c.name("help")
+                    .description("List the commands or show how to use one")
+                    .create_option(|o| {
+                        o.name("command")
+                            .description("The command to show")
+                            .kind(CommandOptionType::String)
+                            .required(false)
+                    })
+            })
+            .create_application_command(|c| c.name("ping").description("Check the bot is alive"))
+            .create_application_command(|c| {
+                c.name("userinfo")
+                    .description("Show information about a member")
+                    .create_option(|o| {
+                        o.name("user")
+                            .description("The member to look up, defaults to you")
+                            .kind(CommandOptionType::User)
+                            .required(false)
+                    })
+            })
+            .create_application_command(|c| {
+                c.name("join")
+                    .description("Join the voice channel you are in")
+            })
+            .create_application_command(|c| {
+                c.name("play")
+                    .description("Play a Youtube video, playlist or search result")
+                    .create_option(|o| {
+                        o.name("query")
+                            .description("A Youtube url or something to search for")
+                            .kind(CommandOptionType::String)
+                            .required(true)
+                            .set_autocomplete(true)
+                    })
+            })
+            .create_application_command(|c| {
+                c.name("playnext")
+                    .description("Play a Youtube video or search result after the current track")
+                    .create_option(|o| {
+                        o.name("query")
+                            .description("A Youtube url or something to search for")
+                            .kind(CommandOptionType::String)
+                            .required(true)
+                            .set_autocomplete(true)
+                    })
+            })
+            .create_application_command(|c| {
+                c.name("playnow")
+                    .description("Interrupt the current track, which then plays next")
+                    .create_option(|o| {
+                        o.name("query")
+                            .description("A Youtube url or something to search for")
+                            .kind(CommandOptionType::String)
+                            .required(true)
+                            .set_autocomplete(true)
+                    })
+            })
+            .create_application_command(|c| {
+                c.name("playing")
+                    .description("Show the track that is currently playing")
+            })
+            .create_application_command(|c| {
+                c.name("queue")
+                    .description("Show the queue")
+                    .create_option(|o| {
+                        o.name("page")
+                            .description("The page of the queue to open")
+                            .kind(CommandOptionType::Integer)
+                            .min_int_value(1)
+                            .required(false)
+                    })
+            })
+            .create_application_command(|c| c.name("stop").description("Stop and clear the queue"))
+            .create_application_command(|c| c.name("skip").description("Skip the current track"))
+            .create_application_command(|c| c.name("pause").description("Pause the current track"))
+            .create_application_command(|c| {
+                c.name("resume").description("Resume the current track")
+            })
+            .create_application_command(|c| {
+                c.name("loop")
+                    .description("Loop the current track, or stop looping it")
+            })
+            .create_application_command(|c| {
+                c.name("shuffle").description("Shuffle the upcoming tracks")
+            })
+            .create_application_command(|c| {
+                c.name("volume")
+                    .description("Show the volume, or set it in percent")
+                    .create_option(|o| {
+                        o.name("level")
+                            .description("The new volume")
+                            .kind(CommandOptionType::Integer)
+                            .min_int_value(0)
+                            .max_int_value(MAX_VOLUME)
+                            .required(false)
+                    })
+            })
+            .create_application_command(|c| {
+                c.name("filter")
+                    .description("Show the filters, apply a preset, or turn all filters off")
+                    .create_option(|o| {
+                        o.name("preset")
+                            .description("The preset to apply")
+                            .kind(CommandOptionType::String)
+                            .required(false);
+                        for preset in FILTER_PRESETS {
+                            o.add_string_choice(preset, preset);
+                        }
+                        o.add_string_choice("off", "off")
+                    })
+            })
+            .create_application_command(|c| {
+                c.name("speed")
+                    .description("Show the playback speed, or change it without changing the pitch")
+                    .create_option(|o| {
+                        o.name("factor")
+                            .description("How much faster to play, 1 is normal")
+                            .kind(CommandOptionType::Number)
+                            .min_number_value(MIN_SPEED)
+                            .max_number_value(MAX_SPEED)
+                            .required(false)
+                    })
+            })
+            .create_application_command(|c| {
+                c.name("pitch")
+                    .description("Show the pitch, or change it without changing the speed")
+                    .create_option(|o| {
+                        o.name("factor")
+                            .description("How much higher to play, 1 is normal")
+                            .kind(CommandOptionType::Number)
+                            .min_number_value(MIN_SPEED)
+                            .max_number_value(MAX_SPEED)
+                            .required(false)
+                    })
+            })
+            .create_application_command(|c| {
+                c.name("eq")
+                    .description("Show the equalizer, apply a preset, or set bands")
+                    .create_option(|o| {
+                        o.name("bands")
+                            .description(format!(
+                                "{}, off, or bands in Hz:dB like 60:4 1000:-2",
+                                EQ_PRESETS.join(", ")
+                            ))
+                            .kind(CommandOptionType::String)
+                            .required(false)
+                    })
+            })
+            .create_application_command(|c| {
+                c.name("chapters")
+                    .description("List the chapters of the current track")
+            })
+            .create_application_command(|c| {
+                c.name("chapter")
+                    .description("Jump to another chapter of the current track")
+                    .create_option(|o| {
+                        o.name("to")
+                            .description("next, prev or a chapter number")
+                            .kind(CommandOptionType::String)
+                            .required(true)
+                    })
+            })
+            .create_application_command(|c| {
+                c.name("lyrics")
+                    .description("Show lyrics for the current track or a search")
+                    .create_option(|o| {
+                        o.name("query")
+                            .description("Artist - Title to search for instead")
+                            .kind(CommandOptionType::String)
+                            .required(false)
+                    })
+            })
+            .create_application_command(|c| {
+                c.name("config")
+                    .description("Show or change this server's settings")
+                    .default_member_permissions(Permissions::MANAGE_GUILD)
+                    .create_option(|o| {
+                        o.name("action")
+                            .description("What to do")
+                            .kind(CommandOptionType::String)
+                            .required(true)
+                            .add_string_choice("show", "show")
+                            .add_string_choice("set", "set")
+                            .add_string_choice("reset", "reset")
+                    })
+                    .create_option(|o| {
+                        o.name("key")
+                            .description("The setting to change")
+                            .kind(CommandOptionType::String)
+                            .required(false);
+                        for key in SETTING_KEYS {
+                            o.add_string_choice(key, key);
+                        }
+                        o
+                    })
+                    .create_option(|o| {
+                        o.name("value")
+                            .description("The new value")
+                            .kind(CommandOptionType::String)
+                            .required(false)
+                    })
+            })
+            .create_application_command(|c| {
+                c.name("prefix")
+                    .description("Show or change the prefix for text commands")
+                    .create_option(|o| {
+                        o.name("set")
+                            .description("The new prefix, requires Manage Server")
+                            .kind(CommandOptionType::String)
+                            .required(false)
+                    })
+                    .create_option(|o| {
+                        o.name("reset")
+                            .description("Go back to the default prefix, requires Manage Server")
+                            .kind(CommandOptionType::Boolean)
+                            .required(false)
+                    })
+            })
+            .create_application_command(|c| {
+                c.name("setup")
+                    .description("Create a song request channel with a player")
+                    .default_member_permissions(Permissions::MANAGE_GUILD)
+            })
+            .create_application_command(|c| {
+                c.name("cache")
+                    .description("Show the audio cache, bot owners only")
+                    .create_option(|o| {
+                        o.name("purge")
+                            .description("Delete every cached track")
+                            .kind(CommandOptionType::Boolean)
+                            .required(false)
+                    })
+            })
+    })
+    .await
+}
+
+fn option<'a>(
+    command: &'a ApplicationCommandInteraction,
+    name: &str,
+) -> Option<&'a CommandDataOption> {
+    command.data.options.iter().find(|o| o.name == name)
+}
+
+fn string_option(command: &ApplicationCommandInteraction, name: &str) -> Option<String> {
+    option(command, name)?
+        .value
+        .as_ref()?
+        .as_str()
+        .map(|value| value.to_string())
+}
+
+fn integer_option(command: &ApplicationCommandInteraction, name: &str) -> Option<i64> {
+    option(command, name)?.value.as_ref()?.as_i64()
+}
+
+fn number_option(command: &ApplicationCommandInteraction, name: &str) -> Option<f64> {
+    option(command, name)?.value.as_ref()?.as_f64()
+}
+
+fn user_option(command: &ApplicationCommandInteraction, name: &str) -> Option<User> {
+    match option(command, name)?.resolved.as_ref()? {
+        CommandDataOptionValue::User(user, _) => Some(user.clone()),
+        _ => None,
+    }
+}
+
+/// Whether the slash command `name` is in the `Music` group, whose channel
+/// check it shares.
+fn is_music_command(name: &str) -> bool {
+    find_command(&[&MUSIC_GROUP], name).is_some()
+}
+
+pub async fn dispatch(ctx: &Context, command: ApplicationCommandInteraction) {
+    if let Err(why) = command.defer(&ctx.http).await {
+        warn!("Failed to defer /{}: {:?}", command.data.name, why);
+        return;
+    }
+
+    let invocation = Invocation::Slash(&command);
+
+    if command.guild_id.is_none() {
+        if let Err(why) = invocation
+            .say(ctx, "This command can only be used in a guild!")
+            .await
+        {
+            warn!("Failed to reply to /{}: {:?}", command.data.name, why);
+        }
+        return;
+    }
+
+    let allowed = if is_music_command(&command.data.name) {
+        check_music_channel(ctx, &invocation).await
+    } else {
+        Ok(())
+    };
+
+    // The framework only limits prefix commands, apply the same buckets here.
+    let allowed = match find_command(&GROUPS, &command.data.name).and_then(|c| c.options.bucket) {
+        Some(bucket) if allowed.is_ok() => check_bucket(ctx, &invocation, bucket).await,
+        _ => allowed,
+    };
+
+    let result: CaitlynResult = match command.data.name.as_str() {
+        _ if allowed.is_err() => allowed,
+        "help" => _help(ctx, &invocation, string_option(&command, "command")).await,
+        "ping" => _ping(ctx, &invocation).await,
+        "userinfo" => {
+            let user = user_option(&command, "user").unwrap_or_else(|| command.user.clone());
+            _userinfo(ctx, &invocation, &user).await
+        }
+        "join" => _join(ctx, &invocation, true).await,
+        "play" => {
+            let query = string_option(&command, "query").unwrap_or_default();
+            _play(ctx, &invocation, query).await
+        }
+        "playnext" => {
+            let query = string_option(&command, "query").unwrap_or_default();
+            _play_at(ctx, &invocation, query, Placement::Next).await
+        }
+        "playnow" => {
+            let query = string_option(&command, "query").unwrap_or_default();
+            _play_at(ctx, &invocation, query, Placement::Now).await
+        }
+        "playing" => _playing(ctx, &invocation).await,
+        "queue" => {
+            let page = integer_option(&command, "page").unwrap_or(1);
+            _queue(ctx, &invocation, page).await
+        }
+        "stop" => _stop(ctx, &invocation).await,
+        "skip" => _skip(ctx, &invocation).await,
+        "pause" => _pause(ctx, &invocation, true).await,
+        "resume" => _pause(ctx, &invocation, false).await,
+        "loop" => _loop(ctx, &invocation).await,
+        "shuffle" => _shuffle(ctx, &invocation).await,
+        "volume" => {
+            let percent = integer_option(&command, "level").map(|level| level as u8);
+            _volume(ctx, &invocation, percent).await
+        }
+        "filter" => _filter(ctx, &invocation, string_option(&command, "preset")).await,
+        "speed" => _speed(ctx, &invocation, number_option(&command, "factor")).await,
+        "pitch" => _pitch(ctx, &invocation, number_option(&command, "factor")).await,
+        "eq" => _eq(ctx, &invocation, string_option(&command, "bands")).await,
+        "chapters" => _chapters(ctx, &invocation).await,
+        "chapter" => {
+            let to = string_option(&command, "to").unwrap_or_default();
+            match ChapterTarget::parse(&to) {
+                Some(target) => _chapter(ctx, &invocation, target).await,
+                None => Err(Error::BadArgument(format!(
+                    "`{}` is not a chapter, use next, prev or a chapter number.",
+                    to
+                ))),
+            }
+        }
+        "lyrics" => _lyrics(ctx, &invocation, string_option(&command, "query")).await,
+        "config" => {
+            let key = string_option(&command, "key").unwrap_or_default();
+            let action = match string_option(&command, "action").as_deref() {
+                Some("set") => {
+                    ConfigAction::Set(key, string_option(&command, "value").unwrap_or_default())
+                }
+                Some("reset") => ConfigAction::Reset(key),
+                _ => ConfigAction::Show,
+            };
+            _config(ctx, &invocation, action).await
+        }
+        "prefix" => {
+            let reset = option(&command, "reset")
+                .and_then(|o| o.value.as_ref())
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+            let action = match string_option(&command, "set") {
+                _ if reset => PrefixAction::Reset,
+                Some(prefix) => PrefixAction::Set(prefix),
+                None => PrefixAction::Show,
+            };
+            _prefix(ctx, &invocation, action).await
+        }
+        "setup" => _setup(ctx, &invocation).await,
+        "cache" => {
+            let purge = option(&command, "purge")
+                .and_then(|o| o.value.as_ref())
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+            let action = if purge {
+                CacheAction::Purge
+            } else {
+                CacheAction::Show
+            };
+            _cache(ctx, &invocation, action).await
+        }
+        _ => Ok(()),
+    };
+
+    if let Err(why) = result {
+        error::report(ctx, &invocation, &command.data.name, &why.into()).await;
+    }
+}
+
+async fn search_suggestions(query: &str) -> reqwest::Result<Vec<String>> {
+    let url = format!(
+        "https://suggestqueries.google.com/complete/search?client=firefox&ds=yt&q={}",
+        urlencoding::encode(query)
+    );
+    let response: serde_json::Value = reqwest::get(url).await?.json().await?;
+
+    Ok(response
+        .get(1)
+        .and_then(|suggestions| suggestions.as_array())
+        .map(|suggestions| {
+            suggestions
+                .iter()
+                .filter_map(|s| s.as_str().map(|s| s.to_string()))
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+pub async fn autocomplete(ctx: &Context, autocomplete: AutocompleteInteraction) {
+    if !["play", "playnext", "playnow"].contains(&autocomplete.data.name.as_str()) {
+        return;
+    }
+
+    let partial = autocomplete
+        .data
+        .options
+        .iter()
+        .find(|o| o.focused)
+        .and_then(|o| o.value.as_ref())
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .to_string();
+
+    if partial.trim().is_empty() || partial.starts_with("http") {
+        let _ = autocomplete
+            .create_autocomplete_response(&ctx.http, |r| r)
+            .await;
+        return;
+    }
+
+    let mut choices = vec![partial.to_owned()];
+    match search_suggestions(&partial).await {
+        Ok(suggestions) => choices.extend(suggestions.into_iter().filter(|s| *s != partial)),
+        Err(why) => warn!("Failed to fetch suggestions for {}: {:?}", partial, why),
+    }
+
+    let _ = autocomplete
+        .create_autocomplete_response(&ctx.http, |r| {
+            for choice in choices.iter().take(AUTOCOMPLETE_LIMIT) {
+                let choice = truncate(choice, CHOICE_LIMIT);
+                r.add_string_choice(&choice, &choice);
+            }
+            r
+        })
+        .await;
+}
@@ -0,0 +1,459 @@
+use serde::Deserialize;
+use serenity::model::id::{RoleId, UserId};
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+pub const DEFAULT_CONFIG_PATH: &str = "caitlyn.toml";
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid { key: String, reason: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, why) => {
+                write!(f, "could not read {}: {}", path.display(), why)
+            }
+            ConfigError::Parse(path, why) => write!(f, "invalid {}: {}", path.display(), why),
+            ConfigError::Invalid { key, reason } => write!(f, "invalid `{}`: {}", key, reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+fn invalid(key: &str, reason: impl ToString) -> ConfigError {
+    ConfigError::Invalid {
+        key: key.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ColourValue {
+    Number(u32),
+    Hex(String),
+}
+
+impl ColourValue {
+    fn resolve(&self, key: &str) -> Result<u32, ConfigError> {
+        let colour = match self {
+            ColourValue::Number(n) => *n,
+            ColourValue::Hex(hex) => parse_colour(hex).map_err(|reason| invalid(key, reason))?,
+        };
+        if colour > 0xFFFFFF {
+            return Err(invalid(key, "colours must be between #000000 and #FFFFFF"));
+        }
+        Ok(colour)
+    }
+}
+
+pub fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        _ => Err(format!("expected true or false, got \"{}\"", value)),
+    }
+}
+
+pub fn parse_colour(value: &str) -> Result<u32, String> {
+    let hex = value
+        .trim()
+        .trim_start_matches('#')
+        .trim_start_matches("0x");
+    match u32::from_str_radix(hex, 16) {
+        Ok(colour) if hex.len() == 6 => Ok(colour),
+        _ => Err(format!(
+            "expected a hex colour like #FFC0CB, got \"{}\"",
+            value
+        )),
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawColours {
+    primary: Option<ColourValue>,
+    error: Option<ColourValue>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawTimeouts {
+    queue: Option<u64>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawLimits {
+    queue_page_size: Option<usize>,
+    queue_invoker_only: Option<bool>,
+    playlist_size: Option<usize>,
+    audio_cache_mb: Option<u64>,
+    track_retries: Option<u32>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawTitles {
+    noise: Vec<String>,
+    suffixes: Vec<String>,
+    keep: Vec<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawBucket {
+    delay: Option<u64>,
+    limit: Option<u32>,
+    time_span: Option<u64>,
+    per: Option<String>,
+    await_ratelimits: Option<u32>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawRateLimits {
+    bypass_roles: Vec<u64>,
+    play: RawBucket,
+    embeds: RawBucket,
+    control: RawBucket,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawConfig {
+    token: Option<String>,
+    prefix: Option<String>,
+    owners: Vec<u64>,
+    icon_url: Option<String>,
+    data_dir: Option<PathBuf>,
+    lyrics_dir: Option<PathBuf>,
+    segments_file: Option<PathBuf>,
+    colours: RawColours,
+    timeouts: RawTimeouts,
+    limits: RawLimits,
+    titles: RawTitles,
+    ratelimits: RawRateLimits,
+}
+
+#[derive(Debug, Clone)]
+pub struct Colours {
+    pub primary: u32,
+    pub error: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Timeouts {
+    pub queue: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct Limits {
+    pub queue_page_size: usize,
+    pub queue_invoker_only: bool,
+    pub playlist_size: usize,
+    /// How many megabytes of tracks are kept on disk, 0 to not keep any.
+    pub audio_cache_mb: u64,
+    /// How often a track that fails mid-play is restarted before it is
+    /// skipped.
+    pub track_retries: u32,
+}
+
+/// Words added to the default title cleaning rules, see `TitleRules`.
+#[derive(Debug, Clone, Default)]
+pub struct TitleWords {
+    pub noise: Vec<String>,
+    pub suffixes: Vec<String>,
+    pub keep: Vec<String>,
+}
+
+/// Who shares a rate limit bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BucketScope {
+    User,
+    Channel,
+    Guild,
+}
+
+/// The limits of one command bucket: at least `delay` between two uses, and
+/// at most `limit` uses per `time_span` (no window when `limit` is 0). Up to
+/// `await_ratelimits` limited commands wait their turn instead of failing.
+#[derive(Debug, Clone)]
+pub struct BucketLimits {
+    pub delay: u64,
+    pub limit: u32,
+    pub time_span: u64,
+    pub per: BucketScope,
+    pub await_ratelimits: u32,
+}
+
+impl RawBucket {
+    fn resolve(&self, key: &str, default: BucketLimits) -> Result<BucketLimits, ConfigError> {
+        let per = match self.per.as_deref() {
+            None => default.per,
+            Some("user") => BucketScope::User,
+            Some("channel") => BucketScope::Channel,
+            Some("guild") => BucketScope::Guild,
+            Some(other) => {
+                return Err(invalid(
+                    &format!("{}.per", key),
+                    format!("expected user, channel or guild, got \"{}\"", other),
+                ))
+            }
+        };
+        let limits = BucketLimits {
+            delay: self.delay.unwrap_or(default.delay),
+            limit: self.limit.unwrap_or(default.limit),
+            time_span: self.time_span.unwrap_or(default.time_span),
+            per,
+            await_ratelimits: self.await_ratelimits.unwrap_or(default.await_ratelimits),
+        };
+        if limits.limit > 0 && limits.time_span == 0 {
+            return Err(invalid(
+                &format!("{}.time_span", key),
+                "must be at least 1 second when a limit is set",
+            ));
+        }
+        Ok(limits)
+    }
+}
+
+/// Rate limits of the commands. Members with one of `bypass_roles`,
+/// the Manage Server permission or bot ownership are never limited.
+#[derive(Debug, Clone)]
+pub struct RateLimits {
+    pub bypass_roles: HashSet<RoleId>,
+    pub play: BucketLimits,
+    pub embeds: BucketLimits,
+    pub control: BucketLimits,
+}
+
+impl RateLimits {
+    /// The limits of the bucket called `name` in command attributes.
+    pub fn bucket(&self, name: &str) -> Option<&BucketLimits> {
+        match name {
+            "play" => Some(&self.play),
+            "embeds" => Some(&self.embeds),
+            "control" => Some(&self.control),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub token: String,
+    pub prefix: String,
+    pub owners: HashSet<UserId>,
+    pub icon_url: String,
+    pub data_dir: PathBuf,
+    pub lyrics_dir: Option<PathBuf>,
+    pub segments_file: Option<PathBuf>,
+    pub colours: Colours,
+    pub timeouts: Timeouts,
+    pub limits: Limits,
+    pub titles: TitleWords,
+    pub ratelimits: RateLimits,
+}
+
+pub const MAX_QUEUE_PAGE_SIZE: usize = 25;
+
+/// Reads `key` from the environment variable `var` when it is set.
+fn env_override<T: FromStr>(var: &str, key: &str, target: &mut Option<T>) -> Result<(), ConfigError>
+where
+    T::Err: fmt::Display,
+{
+    if let Ok(value) = std::env::var(var) {
+        let parsed = value
+            .parse()
+            .map_err(|why| invalid(key, format!("{} (from ${})", why, var)))?;
+        *target = Some(parsed);
+    }
+    Ok(())
+}
+
+impl Config {
+    /// Loads the config file at `path`, if it exists, then applies environment
+    /// variable overrides and validates the result.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let mut raw: RawConfig = if path.exists() {
+            let text = std::fs::read_to_string(path)
+                .map_err(|why| ConfigError::Read(path.to_path_buf(), why))?;
+            toml::from_str(&text).map_err(|why| ConfigError::Parse(path.to_path_buf(), why))?
+        } else {
+            RawConfig::default()
+        };
+
+        env_override("DISCORD_TOKEN", "token", &mut raw.token)?;
+        env_override("CAITLYN_PREFIX", "prefix", &mut raw.prefix)?;
+        env_override("CAITLYN_ICON_URL", "icon_url", &mut raw.icon_url)?;
+        env_override("CAITLYN_DATA_DIR", "data_dir", &mut raw.data_dir)?;
+        env_override("LYRICS_DIR", "lyrics_dir", &mut raw.lyrics_dir)?;
+        env_override("SEGMENTS_FILE", "segments_file", &mut raw.segments_file)?;
+        env_override(
+            "QUEUE_PAGE_SIZE",
+            "limits.queue_page_size",
+            &mut raw.limits.queue_page_size,
+        )?;
+        env_override(
+            "AUDIO_CACHE_MB",
+            "limits.audio_cache_mb",
+            &mut raw.limits.audio_cache_mb,
+        )?;
+        env_override(
+            "TRACK_RETRIES",
+            "limits.track_retries",
+            &mut raw.limits.track_retries,
+        )?;
+        env_override("QUEUE_TIMEOUT", "timeouts.queue", &mut raw.timeouts.queue)?;
+        if let Ok(value) = std::env::var("QUEUE_INVOKER_ONLY") {
+            let parsed = parse_bool(&value).map_err(|why| {
+                invalid(
+                    "limits.queue_invoker_only",
+                    format!("{} (from $QUEUE_INVOKER_ONLY)", why),
+                )
+            })?;
+            raw.limits.queue_invoker_only = Some(parsed);
+        }
+        if let Ok(colour) = std::env::var("CAITLYN_COLOUR") {
+            raw.colours.primary = Some(ColourValue::Hex(colour));
+        }
+        if let Ok(owners) = std::env::var("CAITLYN_OWNERS") {
+            raw.owners = owners
+                .split(',')
+                .filter(|id| !id.trim().is_empty())
+                .map(|id| {
+                    id.trim().parse().map_err(|_| {
+                        invalid(
+                            "owners",
+                            format!("\"{}\" is not a user id (from $CAITLYN_OWNERS)", id),
+                        )
+                    })
+                })
+                .collect::<Result<Vec<u64>, ConfigError>>()?;
+        }
+
+        Config::validate(raw)
+    }
+
+    fn validate(raw: RawConfig) -> Result<Self, ConfigError> {
+        let token = match raw.token {
+            Some(token) if !token.trim().is_empty() => token.trim().to_string(),
+            _ => {
+                return Err(invalid(
+                    "token",
+                    "no bot token, set it in the config file or $DISCORD_TOKEN",
+                ))
+            }
+        };
+
+        let prefix = raw.prefix.unwrap_or_else(|| String::from("~"));
+        if prefix.is_empty() || prefix.chars().any(char::is_whitespace) {
+            return Err(invalid("prefix", "must be non-empty and contain no spaces"));
+        }
+
+        let queue_page_size = raw.limits.queue_page_size.unwrap_or(10);
+        if queue_page_size == 0 || queue_page_size > MAX_QUEUE_PAGE_SIZE {
+            return Err(invalid(
+                "limits.queue_page_size",
+                format!("must be between 1 and {}", MAX_QUEUE_PAGE_SIZE),
+            ));
+        }
+
+        let playlist_size = raw.limits.playlist_size.unwrap_or(200);
+        if playlist_size == 0 {
+            return Err(invalid("limits.playlist_size", "must be at least 1"));
+        }
+
+        let queue_timeout = raw.timeouts.queue.unwrap_or(120);
+        if queue_timeout == 0 {
+            return Err(invalid("timeouts.queue", "must be at least 1 second"));
+        }
+
+        let primary = match &raw.colours.primary {
+            Some(colour) => colour.resolve("colours.primary")?,
+            None => 0xFFC0CB,
+        };
+        let error = match &raw.colours.error {
+            Some(colour) => colour.resolve("colours.error")?,
+            None => 0xED4245,
+        };
+
+        let ratelimits = RateLimits {
+            bypass_roles: raw
+                .ratelimits
+                .bypass_roles
+                .into_iter()
+                .map(RoleId)
+                .collect(),
+            play: raw.ratelimits.play.resolve(
+                "ratelimits.play",
+                BucketLimits {
+                    delay: 3,
+                    limit: 5,
+                    time_span: 60,
+                    per: BucketScope::User,
+                    await_ratelimits: 0,
+                },
+            )?,
+            embeds: raw.ratelimits.embeds.resolve(
+                "ratelimits.embeds",
+                BucketLimits {
+                    delay: 5,
+                    limit: 0,
+                    time_span: 0,
+                    per: BucketScope::User,
+                    await_ratelimits: 0,
+                },
+            )?,
+            control: raw.ratelimits.control.resolve(
+                "ratelimits.control",
+                BucketLimits {
+                    delay: 2,
+                    limit: 10,
+                    time_span: 60,
+                    per: BucketScope::Guild,
+                    await_ratelimits: 0,
+                },
+            )?,
+        };
+
+        Ok(Config {
+            token,
+            prefix,
+            owners: raw.owners.into_iter().map(UserId).collect(),
+            icon_url: raw
+                .icon_url
+                .unwrap_or_else(|| String::from("https://i.imgur.com/vVvNHcj.png")),
+            data_dir: raw.data_dir.unwrap_or_else(|| PathBuf::from("data")),
+            lyrics_dir: raw.lyrics_dir,
+            segments_file: raw.segments_file,
+            colours: Colours { primary, error },
+            timeouts: Timeouts {
+                queue: Duration::from_secs(queue_timeout),
+            },
+            limits: Limits {
+                queue_page_size,
+                queue_invoker_only: raw.limits.queue_invoker_only.unwrap_or(true),
+                playlist_size,
+                audio_cache_mb: raw.limits.audio_cache_mb.unwrap_or(1024),
+                track_retries: raw.limits.track_retries.unwrap_or(2),
+            },
+            titles: TitleWords {
+                noise: raw.titles.noise,
+                suffixes: raw.titles.suffixes,
+                keep: raw.titles.keep,
+            },
+            ratelimits,
+        })
+    }
+}
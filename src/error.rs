@@ -0,0 +1,144 @@
+use crate::commands::invocation::Invocation;
+use crate::storage::guild_settings;
+use serenity::builder::CreateEmbed;
+use serenity::client::Context;
+use serenity::framework::standard::CommandError;
+use std::fmt;
+use std::time::Duration;
+
+/// Errors a command can end with. They are reported back to the user by
+/// [`report`], so their messages are written for people, not logs.
+#[derive(Debug)]
+pub enum Error {
+    /// A track or playlist could not be loaded.
+    Source(String),
+    /// The author needs to be in a voice channel for this.
+    NotInVoice,
+    /// The bot needs to be in a voice channel for this.
+    NotConnected,
+    /// There is no track to control.
+    NothingPlaying,
+    /// Joining the author's voice channel failed.
+    Voice(String),
+    PermissionDenied(String),
+    BadArgument(String),
+    /// Music commands or playback are limited to other channels.
+    WrongChannel(String),
+    /// The command can only be used in a server.
+    GuildOnly,
+    /// The author has to wait this long before using the command again.
+    Cooldown(Duration),
+    Discord(serenity::Error),
+}
+
+pub type CaitlynResult<T = ()> = Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Source(why) => write!(f, "{}", why),
+            Error::NotInVoice => write!(f, "You need to be in a voice channel to do that."),
+            Error::NotConnected => {
+                write!(f, "I'm not in a voice channel, use `join` or `play` first.")
+            }
+            Error::NothingPlaying => write!(f, "Nothing is currently playing."),
+            Error::Voice(why) => write!(f, "{}", why),
+            Error::PermissionDenied(why) => write!(f, "{}", why),
+            Error::BadArgument(why) => write!(f, "{}", why),
+            Error::WrongChannel(why) => write!(f, "{}", why),
+            Error::GuildOnly => write!(f, "This command only works in a server."),
+            Error::Cooldown(wait) => write!(
+                f,
+                "Slow down! You can use this again in {:.1}s.",
+                wait.as_secs_f32()
+            ),
+            Error::Discord(why) => write!(f, "Discord refused the request: {}", why),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Discord(why) => Some(why),
+            _ => None,
+        }
+    }
+}
+
+impl From<serenity::Error> for Error {
+    fn from(why: serenity::Error) -> Self {
+        Error::Discord(why)
+    }
+}
+
+impl Error {
+    fn title(&self) -> &'static str {
+        match self {
+            Error::Source(_) => "Couldn't play that",
+            Error::NotInVoice | Error::NotConnected => "Not in a voice channel",
+            Error::NothingPlaying => "Nothing playing",
+            Error::Voice(_) => "Couldn't join the voice channel",
+            Error::PermissionDenied(_) => "Missing permissions",
+            Error::BadArgument(_) => "Invalid argument",
+            Error::WrongChannel(_) => "Wrong channel",
+            Error::GuildOnly => "Server only",
+            Error::Cooldown(_) => "On cooldown",
+            Error::Discord(_) => "Something went wrong",
+        }
+    }
+}
+
+/// Logs a failed command and replies to its author with an error embed.
+pub async fn report(
+    ctx: &Context,
+    invocation: &Invocation<'_>,
+    command_name: &str,
+    why: &CommandError,
+) {
+    let (title, description) = match why.downcast_ref::<Error>() {
+        Some(Error::Discord(inner)) => {
+            warn!(
+                "Command '{}' failed talking to Discord: {:?}",
+                command_name, inner
+            );
+            ("Something went wrong", why.to_string())
+        }
+        Some(error) => {
+            info!(
+                "Command '{}' returned error {:?} => {}",
+                command_name, error, error
+            );
+            (error.title(), error.to_string())
+        }
+        None if why.downcast_ref::<serenity::Error>().is_some() => {
+            warn!(
+                "Command '{}' failed talking to Discord: {:?}",
+                command_name, why
+            );
+            (
+                "Something went wrong",
+                format!("Discord refused the request: {}", why),
+            )
+        }
+        None => {
+            warn!(
+                "Command '{}' returned error {:?} => {}",
+                command_name, why, why
+            );
+            ("Something went wrong", why.to_string())
+        }
+    };
+
+    let (config, _) = guild_settings(ctx, invocation.guild_id()).await;
+
+    let mut embed = CreateEmbed::default();
+    embed
+        .colour(config.colours.error)
+        .title(title)
+        .description(description);
+
+    if let Err(why) = invocation.send_embed(ctx, embed).await {
+        warn!("Failed to report error for '{}': {:?}", command_name, why);
+    }
+}
@@ -0,0 +1,50 @@
+use serde::Serialize;
+use serenity::prelude::TypeMapKey;
+use std::io::Write;
+use std::path::PathBuf;
+
+const FAILURES_FILE: &str = "failures.jsonl";
+
+/// How often a track has been restarted after failing, kept in the typemap
+/// of its handle.
+pub struct Retries;
+
+impl TypeMapKey for Retries {
+    type Value = u32;
+}
+
+/// A track that stopped with an error, one JSON line of the failure log.
+#[derive(Serialize, Debug)]
+pub struct TrackFailure {
+    pub guild_id: u64,
+    pub url: String,
+    pub title: String,
+    pub reason: String,
+    /// Seconds into the track it failed at.
+    pub position: f64,
+    /// Which attempt at playing the track failed, counted from 1.
+    pub attempt: u32,
+    /// Whether it was given up on rather than restarted.
+    pub skipped: bool,
+    /// Seconds since the epoch.
+    pub at: u64,
+}
+
+/// Appends `failure` to the failure log in `data_dir`, off the async runtime.
+pub fn record(data_dir: PathBuf, failure: TrackFailure) {
+    tokio::task::spawn_blocking(move || {
+        let result = std::fs::create_dir_all(&data_dir).and_then(|_| {
+            let mut line = serde_json::to_string(&failure)?;
+            line.push('\n');
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(data_dir.join(FAILURES_FILE))?
+                .write_all(line.as_bytes())
+        });
+
+        if let Err(why) = result {
+            warn!("Failed to record {:?}: {:?}", failure, why);
+        }
+    });
+}
@@ -0,0 +1,406 @@
+use crate::Filters;
+use serenity::model::id::GuildId;
+use serenity::prelude::TypeMap;
+use std::fmt;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Sample rate songbird plays at. Presets that change pitch resample to it
+/// first, so `asetrate` works from a known rate.
+pub const SAMPLE_RATE: u32 = 48_000;
+
+pub const MIN_SPEED: f64 = 0.5;
+pub const MAX_SPEED: f64 = 2.0;
+
+/// Parses a speed or pitch factor like `1.25` or `1.25x`, if it is in range.
+pub fn parse_factor(value: &str) -> Option<f64> {
+    value
+        .trim()
+        .trim_end_matches('x')
+        .parse::<f64>()
+        .ok()
+        .filter(|factor| valid_factor(*factor))
+}
+
+pub fn valid_factor(factor: f64) -> bool {
+    (MIN_SPEED..=MAX_SPEED).contains(&factor)
+}
+
+/// Bands can be boosted or cut by this many dB at most.
+pub const MAX_BAND_GAIN: f64 = 20.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterPreset {
+    BassBoost,
+    Nightcore,
+    Vaporwave,
+    EightD,
+    Karaoke,
+}
+
+pub const FILTER_PRESETS: [&str; 5] = ["bassboost", "nightcore", "vaporwave", "8d", "karaoke"];
+
+impl FilterPreset {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "bassboost" | "bass" => Some(FilterPreset::BassBoost),
+            "nightcore" => Some(FilterPreset::Nightcore),
+            "vaporwave" => Some(FilterPreset::Vaporwave),
+            "8d" => Some(FilterPreset::EightD),
+            "karaoke" => Some(FilterPreset::Karaoke),
+            _ => None,
+        }
+    }
+
+    fn chain(self) -> String {
+        match self {
+            FilterPreset::BassBoost => String::from("bass=g=10:f=110:w=0.6"),
+            FilterPreset::Nightcore => resample_by(1.25),
+            FilterPreset::Vaporwave => resample_by(0.8),
+            FilterPreset::EightD => String::from("apulsator=hz=0.125"),
+            FilterPreset::Karaoke => String::from("stereotools=mlev=0.03"),
+        }
+    }
+
+    /// How much faster than the original the preset plays.
+    fn speed(self) -> f64 {
+        match self {
+            FilterPreset::Nightcore => 1.25,
+            FilterPreset::Vaporwave => 0.8,
+            _ => 1.0,
+        }
+    }
+}
+
+impl fmt::Display for FilterPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            FilterPreset::BassBoost => "bassboost",
+            FilterPreset::Nightcore => "nightcore",
+            FilterPreset::Vaporwave => "vaporwave",
+            FilterPreset::EightD => "8d",
+            FilterPreset::Karaoke => "karaoke",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Plays faster or slower by `factor`, shifting the pitch with it.
+fn resample_by(factor: f64) -> String {
+    format!(
+        "asetrate={},aresample={}",
+        (f64::from(SAMPLE_RATE) * factor).round(),
+        SAMPLE_RATE
+    )
+}
+
+/// An equalizer band: `gain` dB around `frequency` Hz, one octave wide.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Band {
+    pub frequency: u32,
+    pub gain: f64,
+}
+
+pub const EQ_PRESETS: [&str; 5] = ["bass", "treble", "vocal", "pop", "rock"];
+
+fn eq_preset(name: &str) -> Option<Vec<Band>> {
+    let bands: &[(u32, f64)] = match name.to_lowercase().as_str() {
+        "bass" => &[(60, 6.0), (170, 4.0), (310, 1.0)],
+        "treble" => &[(3000, 2.0), (6000, 4.0), (12000, 6.0)],
+        "vocal" => &[(170, -2.0), (1000, 3.0), (3000, 4.0), (6000, 1.0)],
+        "pop" => &[
+            (60, -1.0),
+            (310, 2.0),
+            (1000, 4.0),
+            (3000, 2.0),
+            (12000, -1.0),
+        ],
+        "rock" => &[
+            (60, 4.0),
+            (170, 2.0),
+            (1000, -2.0),
+            (6000, 2.0),
+            (12000, 4.0),
+        ],
+        _ => return None,
+    };
+
+    Some(
+        bands
+            .iter()
+            .map(|(frequency, gain)| Band {
+                frequency: *frequency,
+                gain: *gain,
+            })
+            .collect(),
+    )
+}
+
+/// Parses an equalizer preset, or bands like `60:4 1000:-2`.
+pub fn parse_equalizer(value: &str) -> Result<Vec<Band>, String> {
+    if let Some(bands) = eq_preset(value.trim()) {
+        return Ok(bands);
+    }
+
+    value
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|band| !band.is_empty())
+        .map(|band| {
+            let (frequency, gain) = band
+                .split_once(':')
+                .ok_or_else(|| format!("`{}` is not a preset or a band like `60:4`", band))?;
+            let frequency = frequency
+                .trim_end_matches("hz")
+                .parse::<u32>()
+                .ok()
+                .filter(|frequency| (20..=20_000).contains(frequency))
+                .ok_or_else(|| format!("`{}` is not a frequency from 20 to 20000", frequency))?;
+            let gain = gain
+                .trim_end_matches("db")
+                .parse::<f64>()
+                .ok()
+                .filter(|gain| gain.abs() <= MAX_BAND_GAIN)
+                .ok_or_else(|| {
+                    format!(
+                        "`{}` is not a gain from -{} to {}",
+                        gain, MAX_BAND_GAIN, MAX_BAND_GAIN
+                    )
+                })?;
+            Ok(Band { frequency, gain })
+        })
+        .collect()
+}
+
+/// The filters of one guild, applied to every track it plays.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioFilters {
+    pub preset: Option<FilterPreset>,
+    pub speed: f64,
+    pub pitch: f64,
+    pub equalizer: Vec<Band>,
+}
+
+impl Default for AudioFilters {
+    fn default() -> Self {
+        AudioFilters {
+            preset: None,
+            speed: 1.0,
+            pitch: 1.0,
+            equalizer: Vec::new(),
+        }
+    }
+}
+
+impl AudioFilters {
+    pub fn is_empty(&self) -> bool {
+        *self == AudioFilters::default()
+    }
+
+    /// The ffmpeg `-af` argument for these filters, if there are any.
+    pub fn chain(&self) -> Option<String> {
+        let mut filters = Vec::new();
+
+        if let Some(preset) = self.preset {
+            filters.push(preset.chain());
+        }
+        if self.pitch != 1.0 {
+            // Resampling changes pitch and tempo together, so undo the tempo.
+            filters.push(resample_by(self.pitch));
+            filters.push(format!("atempo={}", 1.0 / self.pitch));
+        }
+        if self.speed != 1.0 {
+            filters.push(format!("atempo={}", self.speed));
+        }
+        for band in &self.equalizer {
+            filters.push(format!(
+                "equalizer=f={}:t=o:w=1:g={}",
+                band.frequency, band.gain
+            ));
+        }
+
+        if filters.is_empty() {
+            None
+        } else {
+            Some(format!("aresample={},{}", SAMPLE_RATE, filters.join(",")))
+        }
+    }
+
+    /// Seconds of the original track played per second of output.
+    pub fn speed_factor(&self) -> f64 {
+        self.speed * self.preset.map_or(1.0, FilterPreset::speed)
+    }
+
+    pub fn describe(&self) -> String {
+        let mut parts = Vec::new();
+
+        if let Some(preset) = self.preset {
+            parts.push(preset.to_string());
+        }
+        if self.speed != 1.0 {
+            parts.push(format!("speed {}x", self.speed));
+        }
+        if self.pitch != 1.0 {
+            parts.push(format!("pitch {}x", self.pitch));
+        }
+        if !self.equalizer.is_empty() {
+            let bands: Vec<String> = self
+                .equalizer
+                .iter()
+                .map(|band| format!("{}Hz {:+}dB", band.frequency, band.gain))
+                .collect();
+            parts.push(format!("eq {}", bands.join(", ")));
+        }
+
+        if parts.is_empty() {
+            String::from("none")
+        } else {
+            parts.join(", ")
+        }
+    }
+}
+
+/// The filters of `guild_id`, shared with the sources of its tracks so they
+/// pick up changes when they restart.
+pub async fn guild_filters(data: &RwLock<TypeMap>, guild_id: GuildId) -> Arc<RwLock<AudioFilters>> {
+    let filters = {
+        let data_read = data.read().await;
+
+        data_read
+            .get::<Filters>()
+            .expect("Expected filters in TypeMap.")
+            .clone()
+    };
+
+    let mut filters = filters.write().await;
+    filters.entry(guild_id).or_default().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chains_filters() {
+        let bands = |bands: &[(u32, f64)]| -> Vec<Band> {
+            bands
+                .iter()
+                .map(|(frequency, gain)| Band {
+                    frequency: *frequency,
+                    gain: *gain,
+                })
+                .collect()
+        };
+        let cases: Vec<(AudioFilters, Option<&str>)> = vec![
+            (AudioFilters::default(), None),
+            (
+                AudioFilters {
+                    preset: Some(FilterPreset::BassBoost),
+                    ..Default::default()
+                },
+                Some("aresample=48000,bass=g=10:f=110:w=0.6"),
+            ),
+            (
+                AudioFilters {
+                    preset: Some(FilterPreset::Nightcore),
+                    ..Default::default()
+                },
+                Some("aresample=48000,asetrate=60000,aresample=48000"),
+            ),
+            (
+                AudioFilters {
+                    speed: 1.5,
+                    ..Default::default()
+                },
+                Some("aresample=48000,atempo=1.5"),
+            ),
+            (
+                AudioFilters {
+                    pitch: 2.0,
+                    ..Default::default()
+                },
+                Some("aresample=48000,asetrate=96000,aresample=48000,atempo=0.5"),
+            ),
+            (
+                AudioFilters {
+                    preset: Some(FilterPreset::Vaporwave),
+                    speed: 1.25,
+                    pitch: 0.5,
+                    ..Default::default()
+                },
+                Some(
+                    "aresample=48000,asetrate=38400,aresample=48000,\
+                     asetrate=24000,aresample=48000,atempo=2,atempo=1.25",
+                ),
+            ),
+            (
+                AudioFilters {
+                    speed: 0.75,
+                    equalizer: bands(&[(60, 4.0), (3000, -2.5)]),
+                    ..Default::default()
+                },
+                Some(
+                    "aresample=48000,atempo=0.75,\
+                     equalizer=f=60:t=o:w=1:g=4,equalizer=f=3000:t=o:w=1:g=-2.5",
+                ),
+            ),
+        ];
+
+        for (filters, expected) in cases {
+            assert_eq!(filters.chain().as_deref(), expected, "{:?}", filters);
+        }
+    }
+
+    #[test]
+    fn parses_equalizers() {
+        let cases = [
+            ("bass", Some(vec![(60, 6.0), (170, 4.0), (310, 1.0)])),
+            (
+                " Treble ",
+                Some(vec![(3000, 2.0), (6000, 4.0), (12000, 6.0)]),
+            ),
+            ("60:4 1000:-2", Some(vec![(60, 4.0), (1000, -2.0)])),
+            ("60hz:4db,170:1.5", Some(vec![(60, 4.0), (170, 1.5)])),
+            ("20:20 20000:-20", Some(vec![(20, 20.0), (20000, -20.0)])),
+            ("19:4", None),
+            ("20001:4", None),
+            ("60:20.5", None),
+            ("60:-21", None),
+            ("60", None),
+            ("60:loud", None),
+            ("-60:4", None),
+            ("jazz", None),
+            ("60:4 bass", None),
+        ];
+
+        for (value, expected) in cases {
+            let parsed: Option<Vec<(u32, f64)>> = parse_equalizer(value)
+                .map(|bands| {
+                    bands
+                        .into_iter()
+                        .map(|band| (band.frequency, band.gain))
+                        .collect()
+                })
+                .ok();
+            assert_eq!(parsed, expected, "{}", value);
+        }
+    }
+
+    #[test]
+    fn parses_factors() {
+        let cases = [
+            ("1", Some(1.0)),
+            ("1.25x", Some(1.25)),
+            (" 0.5 ", Some(0.5)),
+            ("2.0", Some(2.0)),
+            ("0.49", None),
+            ("2.01", None),
+            ("-1", None),
+            ("fast", None),
+            ("", None),
+        ];
+
+        for (value, expected) in cases {
+            assert_eq!(parse_factor(value), expected, "{}", value);
+        }
+    }
+}
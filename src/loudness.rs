@@ -0,0 +1,181 @@
+use crate::filters::SAMPLE_RATE;
+use crate::storage::BackgroundFile;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+pub const LOUDNESS_CACHE_FILE: &str = "loudness.json";
+
+/// What tracks are normalized to: integrated loudness in LUFS, true peak in
+/// dBTP and loudness range in LU, as in the EBU R128 recommendation for
+/// streaming.
+const TARGET_LOUDNESS: f64 = -16.0;
+const TARGET_PEAK: f64 = -1.5;
+const TARGET_RANGE: f64 = 11.0;
+
+/// The loudness of a track as measured by ffmpeg's `loudnorm`, enough to
+/// normalize it in one linear pass next time.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct Measurement {
+    pub input_i: f64,
+    pub input_tp: f64,
+    pub input_lra: f64,
+    pub input_thresh: f64,
+    pub target_offset: f64,
+}
+
+/// The ffmpeg filters that normalize a track. With a measurement the whole
+/// track gets the same gain; without one `loudnorm` adjusts it as it plays,
+/// which is less accurate but needs no analysis up front.
+pub fn normalize_chain(measurement: Option<&Measurement>) -> String {
+    let loudnorm = match measurement {
+        Some(m) => format!(
+            "loudnorm=I={}:TP={}:LRA={}:measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}:linear=true",
+            TARGET_LOUDNESS,
+            TARGET_PEAK,
+            TARGET_RANGE,
+            m.input_i,
+            m.input_tp,
+            m.input_lra,
+            m.input_thresh,
+            m.target_offset
+        ),
+        None => format!(
+            "loudnorm=I={}:TP={}:LRA={}",
+            TARGET_LOUDNESS, TARGET_PEAK, TARGET_RANGE
+        ),
+    };
+
+    // loudnorm works at 192 kHz, bring it back down for songbird.
+    format!("{},aresample={}", loudnorm, SAMPLE_RATE)
+}
+
+/// Reads the JSON summary `loudnorm` prints at the end of ffmpeg's output.
+fn parse_measurement(output: &str) -> Option<Measurement> {
+    let start = output.rfind('{')?;
+    let end = start + output[start..].find('}')?;
+    let value: serde_json::Value = serde_json::from_str(&output[start..=end]).ok()?;
+
+    // ffmpeg prints the numbers as strings.
+    let field = |name: &str| -> Option<f64> {
+        value
+            .get(name)?
+            .as_str()?
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|number| number.is_finite())
+    };
+
+    Some(Measurement {
+        input_i: field("input_i")?,
+        input_tp: field("input_tp")?,
+        input_lra: field("input_lra")?,
+        input_thresh: field("input_thresh")?,
+        target_offset: field("target_offset")?,
+    })
+}
+
+/// Measures the loudness of the audio file at `path`. This decodes the whole
+/// track, so it takes a moment.
+fn measure(path: &Path) -> Result<Measurement, String> {
+    let output = Command::new("ffmpeg")
+        .args(["-hide_banner", "-nostats", "-i"])
+        .arg(path)
+        .arg("-af")
+        .arg(format!(
+            "loudnorm=I={}:TP={}:LRA={}:print_format=json",
+            TARGET_LOUDNESS, TARGET_PEAK, TARGET_RANGE
+        ))
+        .args(["-f", "null", "-"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|why| format!("could not run ffmpeg: {}", why))?;
+
+    parse_measurement(&String::from_utf8_lossy(&output.stderr))
+        .ok_or_else(|| String::from("ffmpeg printed no loudness"))
+}
+
+/// Loudness measurements by track url, kept in a JSON file under the data
+/// directory so tracks are only analysed once.
+pub struct LoudnessCache {
+    file: BackgroundFile,
+    measurements: HashMap<String, Measurement>,
+    /// Tracks being measured right now.
+    pending: HashSet<String>,
+}
+
+impl LoudnessCache {
+    pub fn load(data_dir: &Path) -> std::io::Result<Self> {
+        let path = data_dir.join(LOUDNESS_CACHE_FILE);
+        let measurements = if path.exists() {
+            let text = std::fs::read_to_string(&path)?;
+            serde_json::from_str(&text)
+                .map_err(|why| std::io::Error::new(std::io::ErrorKind::InvalidData, why))?
+        } else {
+            HashMap::new()
+        };
+
+        Ok(LoudnessCache {
+            file: BackgroundFile::new(path),
+            measurements,
+            pending: HashSet::new(),
+        })
+    }
+
+    pub fn get(&self, uri: &str) -> Option<Measurement> {
+        self.measurements.get(uri).copied()
+    }
+
+    fn insert(&mut self, uri: String, measurement: Measurement) {
+        self.measurements.insert(uri, measurement);
+
+        match serde_json::to_string(&self.measurements) {
+            Ok(text) => self.file.write(text),
+            Err(why) => warn!("Failed to save loudness measurements: {:?}", why),
+        }
+    }
+}
+
+/// The measurement of `uri` if it is known. Otherwise starts measuring
+/// `audio`, its file in the audio cache, in the background so later plays of
+/// the track can use it. Tracks that aren't cached yet aren't downloaded again
+/// just to measure them.
+pub async fn cached_or_measure(
+    cache: &Arc<RwLock<LoudnessCache>>,
+    uri: &str,
+    audio: Option<&Path>,
+) -> Option<Measurement> {
+    let audio = {
+        let mut cache = cache.write().await;
+        if let Some(measurement) = cache.get(uri) {
+            return Some(measurement);
+        }
+        let audio = audio?;
+        if !cache.pending.insert(uri.to_string()) {
+            return None;
+        }
+        audio.to_path_buf()
+    };
+
+    let cache = cache.clone();
+    let uri = uri.to_string();
+    tokio::spawn(async move {
+        let result = tokio::task::spawn_blocking(move || measure(&audio)).await;
+
+        let mut cache = cache.write().await;
+        cache.pending.remove(&uri);
+        match result {
+            Ok(Ok(measurement)) => cache.insert(uri, measurement),
+            Ok(Err(why)) => warn!("Failed to measure the loudness of {}: {}", uri, why),
+            Err(why) => warn!("Loudness measurement of {} panicked: {:?}", uri, why),
+        }
+    });
+
+    None
+}
@@ -0,0 +1,241 @@
+use serde::Deserialize;
+use serenity::async_trait;
+use std::collections::HashMap;
+use std::path::Path;
+
+pub type LyricsResult = Result<Option<String>, Box<dyn std::error::Error + Send + Sync>>;
+
+pub struct LyricsQuery {
+    pub artist: Option<String>,
+    pub title: String,
+}
+
+impl LyricsQuery {
+    /// Builds a query from free text, treating "Artist - Title" as both parts.
+    pub fn parse(query: &str) -> Self {
+        match query.split_once(" - ") {
+            Some((artist, title)) => LyricsQuery {
+                artist: Some(artist.trim().to_string()),
+                title: title.trim().to_string(),
+            },
+            None => LyricsQuery {
+                artist: None,
+                title: query.trim().to_string(),
+            },
+        }
+    }
+
+    pub fn display(&self) -> String {
+        match &self.artist {
+            Some(artist) => format!("{} - {}", artist, self.title),
+            None => self.title.to_owned(),
+        }
+    }
+}
+
+#[async_trait]
+pub trait LyricsProvider: Send + Sync {
+    async fn lyrics(&self, query: &LyricsQuery) -> LyricsResult;
+}
+
+#[derive(Deserialize)]
+struct OvhLyrics {
+    lyrics: String,
+}
+
+#[derive(Deserialize)]
+struct OvhSuggestions {
+    data: Vec<OvhSuggestion>,
+}
+
+#[derive(Deserialize)]
+struct OvhSuggestion {
+    title: String,
+    artist: OvhArtist,
+}
+
+#[derive(Deserialize)]
+struct OvhArtist {
+    name: String,
+}
+
+pub struct LyricsOvh {
+    client: reqwest::Client,
+}
+
+impl LyricsOvh {
+    pub fn new() -> Self {
+        LyricsOvh {
+            client: reqwest::Client::new(),
+        }
+    }
+
+    async fn suggest(
+        &self,
+        query: &str,
+    ) -> Result<Option<(String, String)>, Box<dyn std::error::Error + Send + Sync>> {
+        let url = format!(
+            "https://api.lyrics.ovh/suggest/{}",
+            urlencoding::encode(query)
+        );
+        let suggestions: OvhSuggestions = self.client.get(url).send().await?.json().await?;
+
+        Ok(suggestions
+            .data
+            .into_iter()
+            .next()
+            .map(|s| (s.artist.name, s.title)))
+    }
+}
+
+#[async_trait]
+impl LyricsProvider for LyricsOvh {
+    async fn lyrics(&self, query: &LyricsQuery) -> LyricsResult {
+        let (artist, title) = match &query.artist {
+            Some(artist) => (artist.to_owned(), query.title.to_owned()),
+            None => match self.suggest(&query.title).await? {
+                Some(found) => found,
+                None => return Ok(None),
+            },
+        };
+
+        let url = format!(
+            "https://api.lyrics.ovh/v1/{}/{}",
+            urlencoding::encode(&artist),
+            urlencoding::encode(&title)
+        );
+        let response = self.client.get(url).send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let found: OvhLyrics = response.error_for_status()?.json().await?;
+
+        if found.lyrics.trim().is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(found.lyrics.replace("\r\n", "\n")))
+        }
+    }
+}
+
+/// Serves lyrics from memory, keyed by lowercase "artist - title" or title.
+/// Backs the `LYRICS_DIR` option and stands in for the network in tests.
+#[derive(Default)]
+pub struct LocalLyrics {
+    songs: HashMap<String, String>,
+}
+
+impl LocalLyrics {
+    pub fn insert(&mut self, key: &str, lyrics: &str) {
+        self.songs.insert(key.to_lowercase(), lyrics.to_string());
+    }
+
+    /// Loads every `<Artist - Title>.txt` file in `dir`.
+    pub fn from_dir(dir: &Path) -> std::io::Result<Self> {
+        let mut local = LocalLyrics::default();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("txt") {
+                continue;
+            }
+            if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                local.insert(stem, &std::fs::read_to_string(&path)?);
+            }
+        }
+        Ok(local)
+    }
+}
+
+#[async_trait]
+impl LyricsProvider for LocalLyrics {
+    async fn lyrics(&self, query: &LyricsQuery) -> LyricsResult {
+        let by_title = self.songs.get(&query.title.to_lowercase());
+        let found = match &query.artist {
+            Some(_) => self.songs.get(&query.display().to_lowercase()).or(by_title),
+            None => by_title.or_else(|| {
+                let title = format!(" - {}", query.title.to_lowercase());
+                self.songs
+                    .iter()
+                    .find(|(key, _)| key.ends_with(&title))
+                    .map(|(_, lyrics)| lyrics)
+            }),
+        };
+
+        Ok(found.cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pagination::split_pages;
+
+    fn local() -> LocalLyrics {
+        let mut local = LocalLyrics::default();
+        local.insert(
+            "Rick Astley - Never Gonna Give You Up",
+            "We're no strangers to love\nYou know the rules and so do I",
+        );
+        local.insert("Sandstorm", "Du du du du du");
+        local
+    }
+
+    #[test]
+    fn parses_queries() {
+        let query = LyricsQuery::parse(" Rick Astley - Never Gonna Give You Up ");
+        assert_eq!(query.artist.as_deref(), Some("Rick Astley"));
+        assert_eq!(query.title, "Never Gonna Give You Up");
+        assert_eq!(query.display(), "Rick Astley - Never Gonna Give You Up");
+
+        let query = LyricsQuery::parse("Sandstorm");
+        assert_eq!(query.artist, None);
+        assert_eq!(query.display(), "Sandstorm");
+    }
+
+    #[tokio::test]
+    async fn finds_lyrics() {
+        let local = local();
+        let cases = [
+            ("Rick Astley - Never Gonna Give You Up", true),
+            ("rick astley - never gonna give you up", true),
+            // Only the title, matched against "artist - title" keys.
+            ("Never Gonna Give You Up", true),
+            // A wrong artist still finds a song stored by title.
+            ("Darude - Sandstorm", true),
+            ("Sandstorm", true),
+            ("Darude - Feel the Beat", false),
+        ];
+
+        for (query, found) in cases {
+            let lyrics = local.lyrics(&LyricsQuery::parse(query)).await.unwrap();
+            assert_eq!(lyrics.is_some(), found, "{}", query);
+        }
+    }
+
+    #[tokio::test]
+    async fn pages_long_lyrics() {
+        let mut local = local();
+        let verse = "Never gonna give you up, never gonna let you down\n".repeat(200);
+        local.insert("Long Song", &verse);
+
+        let lyrics = local
+            .lyrics(&LyricsQuery::parse("Long Song"))
+            .await
+            .unwrap()
+            .unwrap();
+        let pages = split_pages(&lyrics, 1000);
+
+        assert!(pages.len() > 1);
+        assert!(pages.iter().all(|page| page.chars().count() <= 1000));
+        assert!(pages.iter().all(|page| !page.starts_with('\n')));
+        assert_eq!(pages.join("\n"), lyrics.trim_end());
+    }
+
+    #[test]
+    fn splits_lines_longer_than_a_page() {
+        let pages = split_pages(&"a".repeat(25), 10);
+        assert_eq!(pages, vec!["a".repeat(10), "a".repeat(10), "a".repeat(5)]);
+
+        assert_eq!(split_pages("", 10), vec![String::new()]);
+    }
+}
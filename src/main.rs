@@ -1,24 +1,67 @@
+mod audio_cache;
+mod chapters;
 mod commands;
+mod config;
+mod error;
+mod failures;
+mod filters;
+mod loudness;
+mod lyrics;
+mod pagination;
+mod ratelimit;
+mod reconnect;
+mod resolver;
+mod segments;
+mod source;
+mod storage;
+mod suggest;
+mod title;
+mod transitions;
+mod util;
 
+use crate::audio_cache::AudioCache;
+use crate::commands::chapters::*;
+use crate::commands::controls::*;
+use crate::commands::filters::*;
 use crate::commands::general::*;
+use crate::commands::invocation::Invocation;
 use crate::commands::music::*;
+use crate::commands::player::*;
+use crate::config::{BucketLimits, BucketScope, Config, DEFAULT_CONFIG_PATH};
+use crate::error::Error;
+use crate::filters::AudioFilters;
+use crate::loudness::LoudnessCache;
+use crate::lyrics::{LocalLyrics, LyricsOvh, LyricsProvider};
+use crate::ratelimit::RateLimiter;
+use crate::resolver::MetadataResolver;
+use crate::segments::{LocalSegments, SegmentProvider, SponsorBlock};
+use crate::storage::{guild_settings, GuildStore};
+use crate::suggest::{closest, command_names, find_command};
+use crate::title::TitleRules;
 
 #[macro_use]
 extern crate tracing;
 
+use std::collections::HashMap;
 use std::env;
+use std::path::PathBuf;
 
 use serenity::{
     async_trait,
     client::{Client, Context, EventHandler},
     framework::{
         standard::{
+            buckets::{BucketBuilder, LimitedFor},
             macros::{group, hook},
-            CommandResult,
+            CommandGroup, CommandResult, DispatchError, Reason,
         },
         StandardFramework,
     },
-    model::{channel::Message, gateway::Ready, id::GuildId},
+    http::Http,
+    model::{
+        application::interaction::Interaction, channel::Message, channel::ReactionType,
+        gateway::Ready, id::GuildId, permissions::Permissions,
+    },
 };
 
 use serenity::prelude::*;
@@ -34,33 +77,226 @@ impl EventHandler for Handler {
         info!("cache is ready!");
     }
 
-    async fn ready(&self, _: Context, ready: Ready) {
+    async fn message(&self, ctx: Context, msg: Message) {
+        if handle_request(&ctx, &msg).await {
+            return;
+        }
+
+        let mentioned = msg.content.trim() == format!("<@{}>", ctx.cache.current_user_id())
+            || msg.content.trim() == format!("<@!{}>", ctx.cache.current_user_id());
+        if !mentioned || msg.author.bot {
+            return;
+        }
+
+        let (config, settings) = guild_settings(&ctx, msg.guild_id).await;
+        let _ = msg
+            .reply(
+                &ctx,
+                format!(
+                    "My prefix here is `{}`, or mention me followed by a command.",
+                    settings.prefix(&config)
+                ),
+            )
+            .await;
+    }
+
+    async fn ready(&self, ctx: Context, ready: Ready) {
         info!("{} is connected!", ready.user.name);
+
+        if let Err(why) = commands::slash::register(&ctx).await {
+            warn!("Failed to register slash commands: {:?}", why);
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        match interaction {
+            Interaction::ApplicationCommand(command) => {
+                commands::slash::dispatch(&ctx, command).await
+            }
+            Interaction::Autocomplete(autocomplete) => {
+                commands::slash::autocomplete(&ctx, autocomplete).await
+            }
+            Interaction::MessageComponent(component)
+                if component.data.custom_id.starts_with(PLAYER_BUTTON_PREFIX) =>
+            {
+                handle_button(&ctx, component).await
+            }
+            _ => (),
+        }
+    }
+}
+
+#[hook]
+async fn after(ctx: &Context, msg: &Message, command_name: &str, command_result: CommandResult) {
+    if let Err(why) = command_result {
+        error::report(ctx, &Invocation::Message(msg), command_name, &why).await;
     }
 }
 
 #[hook]
-async fn after(_ctx: &Context, _msg: &Message, command_name: &str, command_result: CommandResult) {
-    match command_result {
-        Err(why) => info!(
-            "Command '{}' returned error {:?} => {}",
-            command_name, why, why
-        ),
-        _ => (),
+async fn unrecognised_command(ctx: &Context, msg: &Message, unrecognised_command_name: &str) {
+    let mut names = command_names(&GROUPS);
+    names.extend(HELP.options.names.iter().copied());
+
+    let suggestion = match closest(unrecognised_command_name, &names) {
+        Some(suggestion) => suggestion,
+        None => return,
+    };
+
+    let (config, settings) = guild_settings(ctx, msg.guild_id).await;
+    let _ = msg
+        .reply(
+            ctx,
+            format!(
+                "There is no `{}` command. Did you mean `{}{}`?",
+                unrecognised_command_name,
+                settings.prefix(&config),
+                suggestion
+            ),
+        )
+        .await;
+}
+
+/// How to call `command_name`, for replies to commands with the wrong number
+/// of arguments.
+fn usage_hint(prefix: &str, command_name: &str) -> String {
+    match find_command(&GROUPS, command_name) {
+        Some(command) => match command.options.usage {
+            Some(usage) => format!("Usage: `{}{} {}`", prefix, command.options.names[0], usage),
+            None => format!(
+                "`{}{}` takes no arguments.",
+                prefix, command.options.names[0]
+            ),
+        },
+        None => format!("See `{}help` for how to use it.", prefix),
     }
 }
 
+#[hook]
+async fn dispatch_error(ctx: &Context, msg: &Message, error: DispatchError, command_name: &str) {
+    let (config, settings) = guild_settings(ctx, msg.guild_id).await;
+    let prefix = settings.prefix(&config);
+
+    let why = match error {
+        DispatchError::LackingPermissions(permissions) => Error::PermissionDenied(format!(
+            "You need the {} permission to use `{}`.",
+            permissions.get_permission_names().join(", "),
+            command_name
+        )),
+        DispatchError::LackingRole => Error::PermissionDenied(format!(
+            "You don't have the role needed to use `{}`.",
+            command_name
+        )),
+        DispatchError::OnlyForOwners => {
+            Error::PermissionDenied(String::from("Only the bot owners can use that."))
+        }
+        DispatchError::OnlyForGuilds => Error::GuildOnly,
+        DispatchError::Ratelimited(info) => {
+            if !info.is_first_try {
+                return;
+            }
+            Error::Cooldown(info.rate_limit)
+        }
+        DispatchError::NotEnoughArguments { min, given } => Error::BadArgument(format!(
+            "`{}` needs at least {} argument(s), but got {}. {}",
+            command_name,
+            min,
+            given,
+            usage_hint(prefix, command_name)
+        )),
+        DispatchError::TooManyArguments { max, given } => Error::BadArgument(format!(
+            "`{}` takes at most {} argument(s), but got {}. {}",
+            command_name,
+            max,
+            given,
+            usage_hint(prefix, command_name)
+        )),
+        DispatchError::CheckFailed("MusicChannel", Reason::User(reason)) => {
+            Error::WrongChannel(reason)
+        }
+        DispatchError::CheckFailed(_, Reason::User(reason))
+        | DispatchError::CheckFailed(_, Reason::UserAndLog { user: reason, .. }) => {
+            Error::PermissionDenied(reason)
+        }
+        error => {
+            debug!("Not dispatching '{}': {:?}", command_name, error);
+            return;
+        }
+    };
+
+    error::report(ctx, &Invocation::Message(msg), command_name, &why.into()).await;
+}
+
+/// Whether the author of `msg` is subject to the command buckets. Bot owners,
+/// server managers and members with a bypass role are not.
+#[hook]
+async fn rate_limited(ctx: &Context, msg: &Message) -> bool {
+    let (config, _) = guild_settings(ctx, msg.guild_id).await;
+
+    if config.owners.contains(&msg.author.id)
+        || Invocation::Message(msg).has_permissions(ctx, Permissions::MANAGE_GUILD)
+    {
+        return false;
+    }
+
+    !msg.member.as_ref().map_or(false, |member| {
+        member
+            .roles
+            .iter()
+            .any(|role| config.ratelimits.bypass_roles.contains(role))
+    })
+}
+
+#[hook]
+async fn rate_limit_delayed(ctx: &Context, msg: &Message) {
+    let _ = msg
+        .react(ctx, ReactionType::Unicode(String::from("\u{23F3}")))
+        .await;
+}
+
+fn bucket<'a>(b: &'a mut BucketBuilder, limits: &BucketLimits) -> &'a mut BucketBuilder {
+    b.delay(limits.delay)
+        .limit_for(match limits.per {
+            BucketScope::User => LimitedFor::User,
+            BucketScope::Channel => LimitedFor::Channel,
+            BucketScope::Guild => LimitedFor::Guild,
+        })
+        .await_ratelimits(limits.await_ratelimits)
+        .delay_action(rate_limit_delayed)
+        .check(rate_limited);
+    if limits.limit > 0 {
+        b.limit(limits.limit).time_span(limits.time_span);
+    }
+    b
+}
+
+#[hook]
+async fn dynamic_prefix(ctx: &Context, msg: &Message) -> Option<String> {
+    let (config, settings) = guild_settings(ctx, msg.guild_id).await;
+
+    Some(settings.prefix(&config).to_string())
+}
+
 #[group]
+#[description = "Information and server settings."]
 #[only_in(guilds)]
-#[commands(ping, userinfo)]
+#[commands(ping, userinfo, config, prefix, setup, cache)]
 struct General;
 
 #[group]
+#[description = "Playing music in voice channels."]
 #[only_in(guilds)]
-#[commands(join, play, playing, queue, stop, skip)]
+#[checks(MusicChannel)]
+#[commands(
+    join, play, playnext, playnow, playing, queue, stop, skip, pause, resume, repeat, shuffle,
+    volume, filter, speed, pitch, eq, chapters, chapter, lyrics
+)]
 struct Music;
 
+static GROUPS: [&CommandGroup; 2] = [&GENERAL_GROUP, &MUSIC_GROUP];
+
 #[allow(dead_code)]
+#[derive(Clone)]
 struct Track {
     url: String,
     requester: String,
@@ -70,7 +306,11 @@ struct Track {
     channel: String,
     date: String,
     duration: Option<Duration>,
+    /// A live stream, as opposed to a video whose length isn't known.
+    live: bool,
     starttime: Option<Duration>,
+    /// The uuid of its songbird handle, to match events to the queue.
+    handle_id: u128,
 }
 
 impl Track {}
@@ -81,32 +321,140 @@ impl TypeMapKey for Queue {
     type Value = Arc<RwLock<Vec<Track>>>;
 }
 
+struct Lyrics;
+
+impl TypeMapKey for Lyrics {
+    type Value = Arc<dyn LyricsProvider>;
+}
+
+struct Segments;
+
+impl TypeMapKey for Segments {
+    type Value = Arc<dyn SegmentProvider>;
+}
+
+struct Configuration;
+
+impl TypeMapKey for Configuration {
+    type Value = Arc<Config>;
+}
+
+struct Guilds;
+
+impl TypeMapKey for Guilds {
+    type Value = Arc<RwLock<GuildStore>>;
+}
+
+struct Titles;
+
+impl TypeMapKey for Titles {
+    type Value = Arc<TitleRules>;
+}
+
+struct Filters;
+
+impl TypeMapKey for Filters {
+    type Value = Arc<RwLock<HashMap<GuildId, Arc<RwLock<AudioFilters>>>>>;
+}
+
+struct AudioFiles;
+
+impl TypeMapKey for AudioFiles {
+    type Value = Arc<RwLock<AudioCache>>;
+}
+
+struct Limiter;
+
+impl TypeMapKey for Limiter {
+    type Value = Arc<RwLock<RateLimiter>>;
+}
+
+struct Resolver;
+
+impl TypeMapKey for Resolver {
+    type Value = Arc<MetadataResolver>;
+}
+
+struct Loudness;
+
+impl TypeMapKey for Loudness {
+    type Value = Arc<RwLock<LoudnessCache>>;
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    dotenv::dotenv().expect("Failed to load .env file");
+    dotenv::dotenv().ok();
 
     tracing_subscriber::fmt::init();
 
-    let token = env::var("DISCORD_TOKEN").expect("Expected a token in the environment");
+    let config_path = env::var("CAITLYN_CONFIG")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(DEFAULT_CONFIG_PATH));
+    let config = match Config::load(&config_path) {
+        Ok(config) => Arc::new(config),
+        Err(why) => {
+            error!("Failed to load config: {}", why);
+            return Err(why.into());
+        }
+    };
+
+    let guilds = GuildStore::load(&config.data_dir)?;
+    let loudness = LoudnessCache::load(&config.data_dir)?;
+    let audio_files =
+        AudioCache::load(&config.data_dir, config.limits.audio_cache_mb * 1024 * 1024)?;
+
+    let lyrics: Arc<dyn LyricsProvider> = match &config.lyrics_dir {
+        Some(dir) => Arc::new(LocalLyrics::from_dir(dir)?),
+        None => Arc::new(LyricsOvh::new()),
+    };
+    let segments: Arc<dyn SegmentProvider> = match &config.segments_file {
+        Some(path) => Arc::new(LocalSegments::from_file(path)?),
+        None => Arc::new(SponsorBlock::new()),
+    };
+
+    let bot_id = Http::new(&config.token).get_current_user().await?.id;
 
     let framework = StandardFramework::new()
-        .configure(|c| c.prefix("~"))
+        .configure(|c| {
+            c.prefixes(Vec::<String>::new())
+                .dynamic_prefix(dynamic_prefix)
+                .on_mention(Some(bot_id))
+                .owners(config.owners.clone())
+        })
+        .bucket("play", |b| bucket(b, &config.ratelimits.play))
+        .await
+        .bucket("embeds", |b| bucket(b, &config.ratelimits.embeds))
+        .await
+        .bucket("control", |b| bucket(b, &config.ratelimits.control))
+        .await
         .after(after)
+        .unrecognised_command(unrecognised_command)
+        .on_dispatch_error(dispatch_error)
+        .help(&HELP)
         .group(&MUSIC_GROUP)
         .group(&GENERAL_GROUP);
 
     let intents = GatewayIntents::all();
 
-    let mut client = Client::builder(&token, intents)
+    let mut client = Client::builder(&config.token, intents)
         .event_handler(Handler)
         .framework(framework)
         .register_songbird()
-        .await
-        .expect("Error, client failed to build");
+        .await?;
 
     {
         let mut data = client.data.write().await;
         data.insert::<Queue>(Arc::new(RwLock::new(Vec::new())));
+        data.insert::<Lyrics>(lyrics);
+        data.insert::<Segments>(segments);
+        data.insert::<Titles>(Arc::new(TitleRules::from_config(&config.titles)));
+        data.insert::<Filters>(Arc::new(RwLock::new(HashMap::new())));
+        data.insert::<Loudness>(Arc::new(RwLock::new(loudness)));
+        data.insert::<AudioFiles>(Arc::new(RwLock::new(audio_files)));
+        data.insert::<Limiter>(Arc::new(RwLock::new(RateLimiter::default())));
+        data.insert::<Resolver>(Arc::new(MetadataResolver::new()));
+        data.insert::<Configuration>(config.clone());
+        data.insert::<Guilds>(Arc::new(RwLock::new(guilds)));
     }
     let _ = client
         .start()
@@ -0,0 +1,126 @@
+pub const EMBED_DESCRIPTION_LIMIT: usize = 4096;
+pub const EMBED_AUTHOR_LIMIT: usize = 256;
+
+/// Splits `text` into pages of at most `limit` characters, breaking on line
+/// boundaries where possible.
+pub fn split_pages(text: &str, limit: usize) -> Vec<String> {
+    let mut pages: Vec<String> = Vec::new();
+    let mut page = String::new();
+
+    for line in text.lines() {
+        let mut line = line.to_string();
+
+        while line.chars().count() > limit {
+            if !page.is_empty() {
+                pages.push(page);
+                page = String::new();
+            }
+            let split_at = line
+                .char_indices()
+                .nth(limit)
+                .map(|(i, _)| i)
+                .unwrap_or(line.len());
+            let rest = line.split_off(split_at);
+            pages.push(line);
+            line = rest;
+        }
+
+        let needed = if page.is_empty() {
+            line.chars().count()
+        } else {
+            page.chars().count() + 1 + line.chars().count()
+        };
+        if needed > limit {
+            pages.push(page);
+            page = String::new();
+        }
+        if !page.is_empty() {
+            page.push('\n');
+        }
+        page.push_str(&line);
+    }
+
+    if !page.trim().is_empty() || pages.is_empty() {
+        pages.push(page);
+    }
+
+    pages
+}
+
+pub const EMBED_TOTAL_LIMIT: usize = 6000;
+
+/// Groups `lines` into pages of at most `max_lines` lines whose joined length
+/// fits in `max_chars`. Lines that could never fit are truncated.
+pub fn paginate_lines(lines: &[String], max_lines: usize, max_chars: usize) -> Vec<String> {
+    let max_lines = max_lines.max(1);
+    let mut pages: Vec<String> = Vec::new();
+    let mut page = String::new();
+    let mut page_lines = 0;
+
+    for line in lines {
+        let line = truncate(line, max_chars);
+        let needed = page.chars().count() + line.chars().count() + 1;
+
+        if page_lines > 0 && (page_lines >= max_lines || needed > max_chars) {
+            pages.push(page);
+            page = String::new();
+            page_lines = 0;
+        }
+        page.push_str(&line);
+        page.push('\n');
+        page_lines += 1;
+    }
+
+    if page_lines > 0 || pages.is_empty() {
+        pages.push(page);
+    }
+
+    pages
+}
+
+pub fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncates_to_the_limit() {
+        let cases = [
+            ("short", 10, "short"),
+            ("exactly10!", 10, "exactly10!"),
+            ("one too many", 11, "one too ma…"),
+            ("ünïcödé text", 5, "ünïc…"),
+            ("", 3, ""),
+        ];
+
+        for (text, max_chars, truncated) in cases {
+            assert_eq!(truncate(text, max_chars), truncated, "{}", text);
+            assert!(truncate(text, max_chars).chars().count() <= max_chars);
+        }
+    }
+
+    #[test]
+    fn paginates_lines() {
+        let lines: Vec<String> = (1..=5).map(|i| format!("line {}", i)).collect();
+
+        let pages = paginate_lines(&lines, 2, 100);
+        assert_eq!(
+            pages,
+            vec!["line 1\nline 2\n", "line 3\nline 4\n", "line 5\n"]
+        );
+
+        let pages = paginate_lines(&lines, 10, 14);
+        assert_eq!(pages.len(), 3);
+        assert!(pages.iter().all(|page| page.chars().count() <= 14));
+
+        assert_eq!(paginate_lines(&[], 10, 100), vec![String::new()]);
+    }
+}
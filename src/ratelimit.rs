@@ -0,0 +1,178 @@
+use crate::commands::invocation::Invocation;
+use crate::config::{BucketLimits, BucketScope};
+use crate::error::{CaitlynResult, Error};
+use crate::storage::guild_settings;
+use crate::Limiter;
+use serenity::client::Context;
+use serenity::model::permissions::Permissions;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Applies the `[ratelimits]` buckets to slash commands and song requests,
+/// which never go through the framework that limits prefix commands. Their
+/// uses are counted apart from prefix commands, and limited ones are always
+/// refused rather than held back.
+#[derive(Default)]
+pub struct RateLimiter {
+    /// Recent uses by bucket and user, channel or guild id, oldest first,
+    /// along with how long the bucket remembers them.
+    uses: HashMap<(&'static str, u64), (Duration, VecDeque<Instant>)>,
+}
+
+impl RateLimiter {
+    /// Counts a use of `bucket` by `key`, or returns how long is left to wait
+    /// when it is limited.
+    pub fn hit(
+        &mut self,
+        bucket: &'static str,
+        key: u64,
+        limits: &BucketLimits,
+    ) -> Result<(), Duration> {
+        let now = Instant::now();
+        let delay = Duration::from_secs(limits.delay);
+        let window = Duration::from_secs(limits.time_span);
+        let remembered = delay.max(window);
+
+        // Every bucket forgets uses after its own time.
+        self.uses.retain(|_, (remembered, uses)| {
+            uses.back()
+                .is_some_and(|used| now.duration_since(*used) < *remembered)
+        });
+        let (_, uses) = self
+            .uses
+            .entry((bucket, key))
+            .or_insert_with(|| (remembered, VecDeque::new()));
+        while uses
+            .front()
+            .is_some_and(|used| now.duration_since(*used) >= remembered)
+        {
+            uses.pop_front();
+        }
+
+        if let Some(last) = uses.back() {
+            let since = now.duration_since(*last);
+            if since < delay {
+                return Err(delay - since);
+            }
+        }
+        if limits.limit > 0 {
+            let in_window: Vec<&Instant> = uses
+                .iter()
+                .filter(|used| now.duration_since(**used) < window)
+                .collect();
+            if in_window.len() >= limits.limit as usize {
+                return Err(window - now.duration_since(*in_window[0]));
+            }
+        }
+
+        uses.push_back(now);
+        Ok(())
+    }
+}
+
+/// Fails with `Error::Cooldown` when `invocation` is over the limits of
+/// `bucket`. Bot owners, server managers and members with a bypass role are
+/// never limited, as with prefix commands.
+pub async fn check_bucket(
+    ctx: &Context,
+    invocation: &Invocation<'_>,
+    bucket: &'static str,
+) -> CaitlynResult {
+    let (config, _) = guild_settings(ctx, invocation.guild_id()).await;
+    let limits = match config.ratelimits.bucket(bucket) {
+        Some(limits) => limits,
+        None => return Ok(()),
+    };
+
+    if config.owners.contains(&invocation.author().id)
+        || invocation.has_permissions(ctx, Permissions::MANAGE_GUILD)
+        || config
+            .ratelimits
+            .bypass_roles
+            .iter()
+            .any(|role| invocation.has_role(ctx, *role))
+    {
+        return Ok(());
+    }
+
+    let key = match limits.per {
+        BucketScope::User => invocation.author().id.0,
+        BucketScope::Channel => invocation.channel_id().0,
+        BucketScope::Guild => invocation
+            .guild_id()
+            .map_or(invocation.channel_id().0, |guild_id| guild_id.0),
+    };
+
+    let limiter = {
+        let data_read = ctx.data.read().await;
+        data_read
+            .get::<Limiter>()
+            .expect("Expected rate limiter in TypeMap.")
+            .clone()
+    };
+    let result = limiter.write().await.hit(bucket, key, limits);
+    result.map_err(Error::Cooldown)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(delay: u64, limit: u32, time_span: u64) -> BucketLimits {
+        BucketLimits {
+            delay,
+            limit,
+            time_span,
+            per: BucketScope::User,
+            await_ratelimits: 0,
+        }
+    }
+
+    #[test]
+    fn limits_uses() {
+        // Delay, limit and time span of the bucket, and whether each of a
+        // quick series of uses gets through.
+        let cases = [
+            ((0, 0, 0), vec![true, true, true]),
+            ((5, 0, 0), vec![true, false, false]),
+            ((0, 2, 60), vec![true, true, false, false]),
+            ((5, 3, 60), vec![true, false, false]),
+        ];
+
+        for ((delay, limit, time_span), expected) in cases {
+            let limits = limits(delay, limit, time_span);
+            let mut limiter = RateLimiter::default();
+            let allowed: Vec<bool> = expected
+                .iter()
+                .map(|_| limiter.hit("play", 1, &limits).is_ok())
+                .collect();
+            assert_eq!(allowed, expected, "{:?}", limits);
+        }
+    }
+
+    #[test]
+    fn waits_out_the_limit() {
+        let mut limiter = RateLimiter::default();
+        let limits = limits(5, 0, 0);
+        limiter.hit("play", 1, &limits).unwrap();
+
+        let wait = limiter.hit("play", 1, &limits).unwrap_err();
+        assert!(wait > Duration::from_secs(4) && wait <= Duration::from_secs(5));
+    }
+
+    #[test]
+    fn keeps_buckets_and_keys_apart() {
+        let mut limiter = RateLimiter::default();
+        let play = limits(0, 1, 60);
+        let embeds = limits(0, 0, 0);
+
+        assert!(limiter.hit("play", 1, &play).is_ok());
+        for _ in 0..3 {
+            assert!(limiter.hit("embeds", 1, &embeds).is_ok());
+            assert!(limiter.hit("play", 1, &play).is_err());
+        }
+        assert!(limiter.hit("play", 2, &play).is_ok());
+        assert!(limiter.hit("embeds", 2, &embeds).is_ok());
+        assert!(limiter.hit("play", 2, &play).is_err());
+    }
+}
@@ -0,0 +1,159 @@
+use crate::commands::player::update_player;
+use serenity::async_trait;
+use serenity::http::Http;
+use serenity::model::id::{ChannelId, GuildId};
+use serenity::model::mention::Mentionable;
+use serenity::prelude::TypeMap;
+use songbird::events::context_data::{DisconnectKind, DisconnectReason};
+use songbird::id::ChannelId as VoiceChannelId;
+use songbird::model::CloseCode;
+use songbird::tracks::PlayMode;
+use songbird::{Event, EventContext, EventHandler as VoiceEventHandler, Songbird};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+/// How long to wait before each attempt at rejoining a dropped voice channel.
+const RECONNECT_BACKOFF: [Duration; 5] = [
+    Duration::from_secs(1),
+    Duration::from_secs(2),
+    Duration::from_secs(5),
+    Duration::from_secs(15),
+    Duration::from_secs(30),
+];
+
+/// Rejoins the voice channel when the connection drops without the bot being
+/// asked to leave or disconnected by a member, holding the current track
+/// until it is back. Songbird
+/// retries a dropped connection once on its own, this takes over when that
+/// fails too.
+#[derive(Clone)]
+pub struct VoiceWatcher {
+    /// Where music was started from, told when reconnecting fails.
+    pub chan_id: ChannelId,
+    pub voice_channel: ChannelId,
+    pub http: Arc<Http>,
+    pub data: Arc<RwLock<TypeMap>>,
+    pub manager: Arc<Songbird>,
+    pub guild_id: GuildId,
+    /// Set while reconnecting, so the disconnects of failed attempts don't
+    /// start more. There is one watcher per Call, so one flag per guild.
+    pub reconnecting: Arc<AtomicBool>,
+}
+
+impl VoiceWatcher {
+    async fn reconnect(&self, channel: VoiceChannelId) {
+        let handler_lock = match self.manager.get(self.guild_id) {
+            Some(handler_lock) => handler_lock,
+            None => return,
+        };
+
+        // Pause the current track, so it carries on from where the
+        // connection dropped rather than playing to nobody.
+        let paused = {
+            let handler = handler_lock.lock().await;
+            match handler.queue().current() {
+                Some(track) => match track.get_info().await {
+                    Ok(info) if info.playing == PlayMode::Play => {
+                        let _ = track.pause();
+                        Some(track)
+                    }
+                    _ => None,
+                },
+                None => None,
+            }
+        };
+
+        for (attempt, delay) in RECONNECT_BACKOFF.iter().enumerate() {
+            tokio::time::sleep(*delay).await;
+            // Left or stopped in the meantime.
+            if self.manager.get(self.guild_id).is_none() {
+                return;
+            }
+
+            let (_, joined) = self.manager.join(self.guild_id, channel).await;
+            match joined {
+                Ok(_) => {
+                    info!(
+                        "Reconnected to voice in {} after {} attempts",
+                        self.guild_id,
+                        attempt + 1
+                    );
+                    if let Some(track) = paused {
+                        let _ = track.play();
+                    }
+                    update_player(&self.http, &self.data, self.guild_id).await;
+                    return;
+                }
+                Err(why) => warn!(
+                    "Reconnect attempt {} in {} failed: {:?}",
+                    attempt + 1,
+                    self.guild_id,
+                    why
+                ),
+            }
+        }
+
+        let _ = self
+            .chan_id
+            .say(
+                &self.http,
+                format!(
+                    "I lost the connection to {} and couldn't get it back. Use join and resume to carry on.",
+                    self.voice_channel.mention()
+                ),
+            )
+            .await;
+        update_player(&self.http, &self.data, self.guild_id).await;
+    }
+}
+
+#[async_trait]
+impl VoiceEventHandler for VoiceWatcher {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        match ctx {
+            EventContext::DriverConnect(_) => {
+                debug!("Voice connected in {}", self.guild_id);
+            }
+            EventContext::DriverReconnect(_) => {
+                info!("Voice reconnected in {}", self.guild_id);
+            }
+            EventContext::DriverDisconnect(disconnect) => {
+                // No reason means the bot left on purpose, and a first
+                // connect that fails is reported by whatever was joining.
+                if disconnect.reason.is_none() || matches!(disconnect.kind, DisconnectKind::Connect)
+                {
+                    return None;
+                }
+                // Disconnected by a moderator, who wants the bot gone.
+                if matches!(
+                    disconnect.reason,
+                    Some(DisconnectReason::WsClosed(Some(CloseCode::Disconnected)))
+                ) {
+                    info!("Disconnected from voice in {} by a member", self.guild_id);
+                    return None;
+                }
+                if self.reconnecting.swap(true, Ordering::SeqCst) {
+                    return None;
+                }
+                warn!(
+                    "Lost the voice connection in {}: {:?}",
+                    self.guild_id, disconnect.reason
+                );
+
+                let channel = disconnect
+                    .channel_id
+                    .unwrap_or_else(|| self.voice_channel.into());
+                let watcher = self.clone();
+                tokio::spawn(async move {
+                    watcher.reconnect(channel).await;
+                    watcher.reconnecting.store(false, Ordering::SeqCst);
+                });
+            }
+            _ => {}
+        }
+
+        None
+    }
+}
@@ -0,0 +1,177 @@
+use crate::chapters::{self, Chapter};
+use crate::filters::SAMPLE_RATE;
+use crate::source::YOUTUBE_DL;
+use crate::util::video_id;
+use futures::stream::{self, StreamExt};
+use songbird::input::error::{Error as InputError, Result as InputResult};
+use songbird::input::Metadata;
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+/// How long looked up track info is used before it is looked up again.
+const METADATA_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// The most tracks kept, the oldest are dropped past it.
+const MAX_CACHED: usize = 5000;
+
+/// How many tracks of a playlist are looked up at once.
+const BATCH_CONCURRENCY: usize = 8;
+
+/// What a track needs before it can be queued.
+#[derive(Clone, Default)]
+pub struct TrackInfo {
+    pub metadata: Metadata,
+    pub chapters: Vec<Chapter>,
+    /// Live streams have no length either, but neither do videos whose
+    /// length couldn't be read.
+    pub live: bool,
+}
+
+/// Looks up track info with ytextract, which only makes a request or two,
+/// falling back to youtube-dl for searches, other sites and videos ytextract
+/// can't read. Results are kept by video id for `METADATA_TTL`.
+pub struct MetadataResolver {
+    client: ytextract::Client,
+    cache: RwLock<HashMap<String, (Instant, TrackInfo)>>,
+}
+
+impl MetadataResolver {
+    pub fn new() -> Self {
+        MetadataResolver {
+            client: ytextract::Client::new(),
+            cache: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn client(&self) -> &ytextract::Client {
+        &self.client
+    }
+
+    pub async fn resolve(&self, uri: &str) -> InputResult<TrackInfo> {
+        let id = video_id(uri);
+        if let Some(id) = id {
+            if let Some(info) = self.cached(id).await {
+                return Ok(info);
+            }
+        }
+
+        let info = match id {
+            Some(id) => match self.from_ytextract(id).await {
+                Ok(info) => info,
+                Err(why) => {
+                    debug!("Falling back to youtube-dl for {}: {}", id, why);
+                    from_ytdl(uri).await?
+                }
+            },
+            None => from_ytdl(uri).await?,
+        };
+
+        // Searches are kept under the video they found, for when it is
+        // queued again by url.
+        if let Some(id) = info.metadata.source_url.as_deref().and_then(video_id) {
+            self.insert(id, info.clone()).await;
+        }
+        Ok(info)
+    }
+
+    /// Looks up `uris` a few at a time, so queueing them afterwards is served
+    /// from the cache.
+    pub async fn prefetch(&self, uris: &[String]) {
+        stream::iter(uris)
+            .for_each_concurrent(BATCH_CONCURRENCY, |uri| async move {
+                if let Err(why) = self.resolve(uri).await {
+                    warn!("Failed to look up {}: {:?}", uri, why);
+                }
+            })
+            .await;
+    }
+
+    async fn cached(&self, id: &str) -> Option<TrackInfo> {
+        let cache = self.cache.read().await;
+        match cache.get(id) {
+            Some((looked_up, info)) if looked_up.elapsed() < METADATA_TTL => Some(info.clone()),
+            _ => None,
+        }
+    }
+
+    async fn insert(&self, id: &str, info: TrackInfo) {
+        let mut cache = self.cache.write().await;
+        cache.retain(|_, (looked_up, _)| looked_up.elapsed() < METADATA_TTL);
+        if cache.len() >= MAX_CACHED {
+            let oldest = cache
+                .iter()
+                .min_by_key(|(_, (looked_up, _))| *looked_up)
+                .map(|(id, _)| id.to_owned());
+            if let Some(oldest) = oldest {
+                cache.remove(&oldest);
+            }
+        }
+
+        cache.insert(id.to_string(), (Instant::now(), info));
+    }
+
+    async fn from_ytextract(&self, id: &str) -> Result<TrackInfo, String> {
+        let id = id
+            .parse()
+            .map_err(|_| format!("`{}` is not a video id", id))?;
+        let video = self.client.video(id).await.map_err(|why| why.to_string())?;
+
+        let live = video.live();
+        let duration = Some(video.duration()).filter(|duration| !live && !duration.is_zero());
+        let metadata = Metadata {
+            title: Some(video.title().to_string()),
+            channel: Some(video.channel().name().to_string()),
+            date: Some(video.date().format("%Y%m%d").to_string()),
+            duration,
+            thumbnail: video
+                .thumbnails()
+                .iter()
+                .max_by_key(|thumbnail| thumbnail.width)
+                .map(|thumbnail| thumbnail.url.to_string()),
+            source_url: Some(format!("https://www.youtube.com/watch?v={}", video.id())),
+            channels: Some(2),
+            sample_rate: Some(SAMPLE_RATE),
+            ..Default::default()
+        };
+
+        // ytextract doesn't read the chapter list, but Youtube makes it from
+        // the description's timestamps anyway.
+        Ok(TrackInfo {
+            metadata,
+            chapters: chapters::from_description(video.description()),
+            live,
+        })
+    }
+}
+
+/// Track info from `youtube-dl -j`, which starts a whole youtube-dl process.
+async fn from_ytdl(uri: &str) -> InputResult<TrackInfo> {
+    let output = tokio::process::Command::new(YOUTUBE_DL)
+        .args(["-j", "--no-playlist", "--ignore-config", "--no-warnings"])
+        .arg(uri)
+        .stdin(Stdio::null())
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(InputError::Metadata);
+    }
+
+    let value: serde_json::Value =
+        serde_json::from_slice(&output.stdout).map_err(|error| InputError::Json {
+            error,
+            parsed_text: String::from_utf8_lossy(&output.stdout).into_owned(),
+        })?;
+    let chapters = chapters::from_ytdl(&value);
+    let live = value
+        .get("is_live")
+        .and_then(|live| live.as_bool())
+        .unwrap_or(false);
+
+    Ok(TrackInfo {
+        metadata: Metadata::from_ytdl_output(value),
+        chapters,
+        live,
+    })
+}
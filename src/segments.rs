@@ -0,0 +1,289 @@
+use crate::filters::AudioFilters;
+use crate::util::format_duration;
+use serde::{Deserialize, Deserializer};
+use serenity::async_trait;
+use serenity::prelude::TypeMapKey;
+use songbird::{Event, EventContext, EventHandler as VoiceEventHandler};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+pub type SegmentResult = Result<Vec<Segment>, Box<dyn std::error::Error + Send + Sync>>;
+
+/// Kinds of segments that are skipped, as SponsorBlock names them.
+pub const SKIPPED_CATEGORIES: [&str; 6] = [
+    "sponsor",
+    "intro",
+    "outro",
+    "selfpromo",
+    "interaction",
+    "music_offtopic",
+];
+
+/// How often a playing track checks whether it is in a segment.
+pub const SEGMENT_TICK: Duration = Duration::from_millis(500);
+
+/// Segments shorter than this aren't worth restarting the track for.
+const MIN_SEGMENT_LENGTH: Duration = Duration::from_secs(1);
+
+/// A part of a video to skip, in the time of the original video.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Segment {
+    #[serde(deserialize_with = "seconds")]
+    pub start: Duration,
+    #[serde(deserialize_with = "seconds")]
+    pub end: Duration,
+    pub category: String,
+}
+
+fn seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let seconds = f64::deserialize(deserializer)?;
+    Ok(Duration::from_secs_f64(seconds.max(0.0)))
+}
+
+#[async_trait]
+pub trait SegmentProvider: Send + Sync {
+    /// The segments of the Youtube video `video_id` to skip, earliest first.
+    async fn segments(&self, video_id: &str) -> SegmentResult;
+}
+
+#[derive(Deserialize)]
+struct SponsorBlockSegment {
+    segment: [f64; 2],
+    category: String,
+}
+
+/// Segments from the public SponsorBlock API.
+pub struct SponsorBlock {
+    client: reqwest::Client,
+}
+
+impl SponsorBlock {
+    pub fn new() -> Self {
+        SponsorBlock {
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl SegmentProvider for SponsorBlock {
+    async fn segments(&self, video_id: &str) -> SegmentResult {
+        let categories = serde_json::to_string(&SKIPPED_CATEGORIES)?;
+        let response = self
+            .client
+            .get("https://sponsor.ajay.app/api/skipSegments")
+            .query(&[("videoID", video_id), ("categories", &categories)])
+            .send()
+            .await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(Vec::new());
+        }
+
+        let found: Vec<SponsorBlockSegment> = response.error_for_status()?.json().await?;
+        let mut segments: Vec<Segment> = found
+            .into_iter()
+            .map(|s| Segment {
+                start: Duration::from_secs_f64(s.segment[0].max(0.0)),
+                end: Duration::from_secs_f64(s.segment[1].max(0.0)),
+                category: s.category,
+            })
+            .collect();
+        segments.sort_by_key(|segment| segment.start);
+
+        Ok(segments)
+    }
+}
+
+/// Serves segments from memory, keyed by video id. Backs the `SEGMENTS_FILE`
+/// option, so a fixture can replace the API.
+#[derive(Default)]
+pub struct LocalSegments {
+    videos: HashMap<String, Vec<Segment>>,
+}
+
+impl LocalSegments {
+    pub fn insert(&mut self, video_id: &str, mut segments: Vec<Segment>) {
+        segments.sort_by_key(|segment| segment.start);
+        self.videos.insert(video_id.to_string(), segments);
+    }
+
+    /// Loads a JSON object of video ids to lists of
+    /// `{"start": 0.0, "end": 12.5, "category": "intro"}`.
+    pub fn from_file(path: &Path) -> std::io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let videos: HashMap<String, Vec<Segment>> = serde_json::from_str(&text)
+            .map_err(|why| std::io::Error::new(std::io::ErrorKind::InvalidData, why))?;
+
+        let mut local = LocalSegments::default();
+        for (video_id, segments) in videos {
+            local.insert(&video_id, segments);
+        }
+        Ok(local)
+    }
+}
+
+#[async_trait]
+impl SegmentProvider for LocalSegments {
+    async fn segments(&self, video_id: &str) -> SegmentResult {
+        Ok(self.videos.get(video_id).cloned().unwrap_or_default())
+    }
+}
+
+/// The segments skipped so far, kept in the typemap of the track's handle.
+pub struct SkippedSegments;
+
+impl TypeMapKey for SkippedSegments {
+    type Value = Vec<Segment>;
+}
+
+/// Seeks past segments as the track it is attached to reaches them.
+pub struct SegmentSkipper {
+    pub segments: Vec<Segment>,
+    pub filters: Arc<RwLock<AudioFilters>>,
+}
+
+#[async_trait]
+impl VoiceEventHandler for SegmentSkipper {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let (state, track) = match ctx {
+            EventContext::Track(tracks) => *tracks.first()?,
+            _ => return None,
+        };
+
+        // Positions are in played time, segments in the time of the video.
+        let speed = self.filters.read().await.speed_factor();
+        let position = state.position.mul_f64(speed);
+
+        let segment = self.segments.iter().find(|segment| {
+            segment.end.saturating_sub(segment.start) >= MIN_SEGMENT_LENGTH
+                && segment.start <= position
+                && position < segment.end
+        })?;
+        if let Err(why) = track.seek_time(segment.end.div_f64(speed)) {
+            warn!("Failed to skip a {} segment: {:?}", segment.category, why);
+            return None;
+        }
+
+        let typemap = track.typemap();
+        let mut typemap = typemap.write().await;
+        let skipped = typemap.entry::<SkippedSegments>().or_insert_with(Vec::new);
+        if !skipped.iter().any(|s| s.start == segment.start) {
+            skipped.push(segment.clone());
+        }
+
+        None
+    }
+}
+
+/// Describes skipped segments for the now playing embed, e.g.
+/// "intro, sponsor (1:05)".
+pub fn describe_skipped(skipped: &[Segment]) -> Option<String> {
+    if skipped.is_empty() {
+        return None;
+    }
+
+    let mut categories: Vec<&str> = Vec::new();
+    for segment in skipped {
+        let category = segment.category.as_str();
+        if !categories.contains(&category) {
+            categories.push(category);
+        }
+    }
+    let total: Duration = skipped
+        .iter()
+        .map(|segment| segment.end.saturating_sub(segment.start))
+        .sum();
+
+    Some(format!(
+        "{} ({})",
+        categories.join(", "),
+        format_duration(total)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(start: u64, end: u64, category: &str) -> Segment {
+        Segment {
+            start: Duration::from_secs(start),
+            end: Duration::from_secs(end),
+            category: category.to_string(),
+        }
+    }
+
+    #[test]
+    fn describes_skipped_segments() {
+        let cases = [
+            (vec![], None),
+            (vec![segment(0, 12, "intro")], Some("intro (0:12)")),
+            (
+                vec![
+                    segment(0, 10, "intro"),
+                    segment(60, 115, "sponsor"),
+                    segment(200, 210, "sponsor"),
+                ],
+                Some("intro, sponsor (1:15)"),
+            ),
+            (
+                vec![segment(100, 3800, "music_offtopic")],
+                Some("music_offtopic (1:01:40)"),
+            ),
+        ];
+
+        for (skipped, expected) in cases {
+            assert_eq!(describe_skipped(&skipped).as_deref(), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn serves_local_segments() {
+        let mut local = LocalSegments::default();
+        local.insert(
+            "dQw4w9WgXcQ",
+            vec![segment(200, 210, "outro"), segment(0, 10, "intro")],
+        );
+
+        let cases = [
+            ("dQw4w9WgXcQ", vec!["intro", "outro"]),
+            ("aaaaaaaaaaa", vec![]),
+        ];
+        for (id, expected) in cases {
+            let found = local.segments(id).await.unwrap();
+            let categories: Vec<&str> = found.iter().map(|s| s.category.as_str()).collect();
+            assert_eq!(categories, expected, "{}", id);
+        }
+    }
+
+    #[tokio::test]
+    async fn loads_segments_file() {
+        let path = std::env::temp_dir().join("caitlyn-segments-test.json");
+        std::fs::write(
+            &path,
+            r#"{"dQw4w9WgXcQ": [
+                {"start": 30.5, "end": 45, "category": "sponsor"},
+                {"start": -1, "end": 5, "category": "intro"}
+            ]}"#,
+        )
+        .unwrap();
+        let local = LocalSegments::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let found = local.segments("dQw4w9WgXcQ").await.unwrap();
+        let found: Vec<(f64, f64, &str)> = found
+            .iter()
+            .map(|s| {
+                (
+                    s.start.as_secs_f64(),
+                    s.end.as_secs_f64(),
+                    s.category.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(found, [(0.0, 5.0, "intro"), (30.5, 45.0, "sponsor")]);
+    }
+}
@@ -0,0 +1,266 @@
+use crate::audio_cache::{self, AudioCache, CachedAudio};
+use crate::filters::AudioFilters;
+use crate::loudness::{cached_or_measure, normalize_chain, LoudnessCache};
+use crate::resolver::{MetadataResolver, TrackInfo};
+use songbird::input::error::{Error as InputError, Result as InputResult};
+use songbird::input::restartable::Restart;
+use songbird::input::{children_to_reader, Codec, Container, Input, Metadata, Restartable};
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+pub const YOUTUBE_DL: &str = "youtube-dl";
+const FFMPEG: &str = "ffmpeg";
+
+const YOUTUBE_DL_ARGS: [&str; 8] = [
+    "-f",
+    "webm[abr>0]/bestaudio/best",
+    "-R",
+    "infinite",
+    "--no-playlist",
+    "--ignore-config",
+    "--no-warnings",
+    "-q",
+];
+
+const FFMPEG_OUTPUT_ARGS: [&str; 9] = [
+    "-f",
+    "s16le",
+    "-ac",
+    "2",
+    "-ar",
+    "48000",
+    "-acodec",
+    "pcm_f32le",
+    "-",
+];
+
+/// Starts downloading the audio of `uri`, to be read from its stdout.
+pub fn spawn_youtube_dl(uri: &str) -> std::io::Result<Child> {
+    Command::new(YOUTUBE_DL)
+        .args(YOUTUBE_DL_ARGS)
+        .arg(uri)
+        .args(["-o", "-"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+}
+
+/// Stops processes that were started for an input that couldn't be built, so
+/// they don't keep downloading with nobody reading.
+fn kill_all(children: Vec<Child>) {
+    for mut child in children {
+        let _ = child.kill();
+        let _ = child.wait();
+    }
+}
+
+/// A youtube-dl stream piped through ffmpeg with the guild's filters. It
+/// reads the filters again every time it (re)starts, so changing them and
+/// seeking to the current position applies them mid-track.
+pub struct YtdlSource {
+    uri: String,
+    filters: Arc<RwLock<AudioFilters>>,
+    resolver: Arc<MetadataResolver>,
+    /// Where loudness measurements are kept, when the track is normalized.
+    loudness: Option<Arc<RwLock<LoudnessCache>>>,
+    /// Where tracks are kept on disk, so replays skip the download.
+    cache: Option<Arc<RwLock<AudioCache>>>,
+    /// Live streams have no end to cache.
+    live: bool,
+    /// What was found while reading the metadata, handed back by
+    /// `into_input` as songbird's metadata has no room for chapters or
+    /// whether the track is live.
+    found: Arc<std::sync::Mutex<TrackInfo>>,
+    /// Where in the output and the original track the last restart was, and
+    /// how fast the output has been going since. Filters that change speed
+    /// make songbird's position drift from the original track's.
+    output_mark: Duration,
+    source_mark: Duration,
+    speed: f64,
+}
+
+impl YtdlSource {
+    /// A source for `uri`, or the first search result when `is_url` is false,
+    /// whose metadata comes from `resolver`.
+    pub fn new(
+        query: &str,
+        is_url: bool,
+        filters: Arc<RwLock<AudioFilters>>,
+        resolver: Arc<MetadataResolver>,
+    ) -> Self {
+        let uri = if is_url {
+            query.to_string()
+        } else {
+            format!("ytsearch1:{}", query)
+        };
+
+        YtdlSource {
+            uri,
+            filters,
+            resolver,
+            loudness: None,
+            cache: None,
+            live: false,
+            found: Arc::default(),
+            output_mark: Duration::ZERO,
+            source_mark: Duration::ZERO,
+            speed: 1.0,
+        }
+    }
+
+    /// Normalizes the loudness of the track, measuring it first if `cache`
+    /// doesn't know it yet.
+    pub fn normalized(mut self, cache: Arc<RwLock<LoudnessCache>>) -> Self {
+        self.loudness = Some(cache);
+        self
+    }
+
+    /// Plays the track from `cache` once it has been played before.
+    pub fn cached(mut self, cache: Arc<RwLock<AudioCache>>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Builds a lazily started, seekable input for this source, along with
+    /// what is known about the track.
+    pub async fn into_input(mut self) -> InputResult<(Restartable, TrackInfo)> {
+        // A seek before the first restart, like a retry resuming where the
+        // last attempt stopped, is at the speed the track plays at now.
+        self.speed = self.filters.read().await.speed_factor();
+        let found = self.found.clone();
+        let input = Restartable::new(self, true).await?;
+
+        let info = found
+            .lock()
+            .map(|mut info| std::mem::take(&mut *info))
+            .unwrap_or_default();
+        Ok((input, info))
+    }
+
+    /// Maps a position in songbird's output to the original track.
+    fn source_position(&self, output: Duration) -> Duration {
+        if output >= self.output_mark {
+            self.source_mark + (output - self.output_mark).mul_f64(self.speed)
+        } else {
+            self.source_mark
+                .saturating_sub((self.output_mark - output).mul_f64(self.speed))
+        }
+    }
+}
+
+#[songbird::async_trait]
+impl Restart for YtdlSource {
+    async fn call_restart(&mut self, time: Option<Duration>) -> InputResult<Input> {
+        let output = time.unwrap_or(Duration::ZERO);
+        let start = if output.is_zero() {
+            Duration::ZERO
+        } else {
+            self.source_position(output)
+        };
+        let filters = self.filters.read().await.clone();
+
+        self.output_mark = output;
+        self.source_mark = start;
+        self.speed = filters.speed_factor();
+
+        let cached = match &self.cache {
+            Some(cache) if !self.live => audio_cache::lookup(cache, &self.uri).await,
+            _ => CachedAudio::Download,
+        };
+        // Loudness is measured from the cached file, never a download of its
+        // own.
+        let audio = match &cached {
+            CachedAudio::File(path) => Some(path.clone()),
+            _ => None,
+        };
+
+        let mut children = Vec::new();
+        let mut filling = None;
+        let mut ffmpeg = Command::new(FFMPEG);
+        if !start.is_zero() {
+            ffmpeg.arg("-ss").arg(format!("{:.3}", start.as_secs_f64()));
+        }
+        match cached {
+            CachedAudio::File(path) => {
+                ffmpeg.arg("-i").arg(path).stdin(Stdio::null());
+            }
+            CachedAudio::Fill(fill) => {
+                // The download goes through the cache on its way to ffmpeg.
+                let youtube_dl = spawn_youtube_dl(&self.uri)?;
+                ffmpeg.args(["-i", "-"]).stdin(Stdio::piped());
+                filling = Some((fill, youtube_dl));
+            }
+            CachedAudio::Download => {
+                let mut youtube_dl = spawn_youtube_dl(&self.uri)?;
+                let stdout = youtube_dl.stdout.take().ok_or(InputError::Stdout)?;
+                ffmpeg.args(["-i", "-"]).stdin(stdout);
+                children.push(youtube_dl);
+            }
+        }
+
+        // Normalize first, so the measurement matches what goes into it.
+        let mut chain = Vec::new();
+        if let Some(cache) = &self.loudness {
+            let measurement = cached_or_measure(cache, &self.uri, audio.as_deref()).await;
+            chain.push(normalize_chain(measurement.as_ref()));
+        }
+        chain.extend(filters.chain());
+        if !chain.is_empty() {
+            ffmpeg.arg("-af").arg(chain.join(","));
+        }
+        let ffmpeg = ffmpeg
+            .args(FFMPEG_OUTPUT_ARGS)
+            .stderr(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn();
+        let mut ffmpeg = match ffmpeg {
+            Ok(ffmpeg) => ffmpeg,
+            Err(why) => {
+                children.extend(filling.map(|(_, youtube_dl)| youtube_dl));
+                kill_all(children);
+                return Err(why.into());
+            }
+        };
+        if let Some((fill, youtube_dl)) = filling {
+            match ffmpeg.stdin.take() {
+                Some(stdin) => fill.start(youtube_dl, stdin),
+                None => {
+                    kill_all(vec![ffmpeg, youtube_dl]);
+                    return Err(InputError::Stdout);
+                }
+            }
+        }
+        children.push(ffmpeg);
+
+        Ok(Input::new(
+            true,
+            children_to_reader::<f32>(children),
+            Codec::FloatPcm,
+            Container::Raw,
+            None,
+        ))
+    }
+
+    async fn lazy_init(&mut self) -> InputResult<(Option<Metadata>, Codec, Container)> {
+        let info = self.resolver.resolve(&self.uri).await?;
+        self.live = info.live;
+        let metadata = info.metadata.clone();
+        if let Ok(mut found) = self.found.lock() {
+            *found = info;
+        }
+
+        // Play the search result from now on, instead of searching again on
+        // every restart.
+        if let Some(url) = &metadata.source_url {
+            self.uri = url.to_owned();
+        }
+        if let Some(cache) = &self.cache {
+            audio_cache::count_play(cache, &self.uri).await;
+        }
+
+        Ok((Some(metadata), Codec::FloatPcm, Container::Raw))
+    }
+}
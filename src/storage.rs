@@ -0,0 +1,502 @@
+use crate::config::{parse_bool, parse_colour, Config, MAX_QUEUE_PAGE_SIZE};
+use crate::transitions::MAX_CROSSFADE;
+use crate::{Configuration, Guilds};
+use serde::{Deserialize, Serialize};
+use serenity::client::Context;
+use serenity::model::id::{ChannelId, GuildId, RoleId};
+use serenity::model::mention::Mentionable;
+use serenity::prelude::TypeMap;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const GUILD_SETTINGS_FILE: &str = "guilds.json";
+
+pub const SETTING_KEYS: [&str; 11] = [
+    "colour",
+    "queue_page_size",
+    "queue_timeout",
+    "queue_invoker_only",
+    "music_channels",
+    "music_voice_channels",
+    "delete_misplaced",
+    "dj_role",
+    "normalize",
+    "crossfade",
+    "skip_segments",
+];
+
+/// Per-guild overrides of the global config. `None` means "use the default".
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+#[serde(default)]
+pub struct GuildSettings {
+    pub prefix: Option<String>,
+    pub colour: Option<u32>,
+    pub queue_page_size: Option<usize>,
+    pub queue_timeout: Option<u64>,
+    pub queue_invoker_only: Option<bool>,
+    /// Text channels the music commands may be used in, any when empty.
+    pub music_channels: Vec<u64>,
+    /// Voice channels the bot may play in, any when empty.
+    pub music_voice_channels: Vec<u64>,
+    /// Whether music commands used in the wrong channel are deleted.
+    pub delete_misplaced: Option<bool>,
+    /// The song request channel made by `setup`, and its player message.
+    pub request_channel: Option<u64>,
+    pub player_message: Option<u64>,
+    /// Members with this role, or Manage Server, control playback. Anyone
+    /// can when it is not set.
+    pub dj_role: Option<u64>,
+    /// Playback volume in percent, kept across tracks.
+    pub volume: Option<u8>,
+    /// Whether tracks are normalized to the same loudness.
+    pub normalize: Option<bool>,
+    /// Seconds the end of a track overlaps the start of the next, off at 0.
+    pub crossfade: Option<u64>,
+    /// Whether sponsors, intros and other non-music segments are skipped.
+    pub skip_segments: Option<bool>,
+}
+
+/// Parses a list of channel mentions or ids, separated by spaces or commas.
+fn parse_channels(value: &str) -> Result<Vec<u64>, String> {
+    value
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|channel| !channel.is_empty())
+        .map(|channel| {
+            channel
+                .trim_start_matches("<#")
+                .trim_end_matches('>')
+                .parse::<u64>()
+                .map_err(|_| format!("\"{}\" is not a channel", channel))
+        })
+        .collect()
+}
+
+fn parse_role(value: &str) -> Result<u64, String> {
+    value
+        .trim()
+        .trim_start_matches("<@&")
+        .trim_end_matches('>')
+        .parse::<u64>()
+        .map_err(|_| format!("\"{}\" is not a role", value))
+}
+
+fn mention_channels(channels: &[u64]) -> String {
+    channels
+        .iter()
+        .map(|channel| ChannelId(*channel).mention().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub const MAX_PREFIX_LENGTH: usize = 10;
+
+impl GuildSettings {
+    pub fn prefix<'a>(&'a self, config: &'a Config) -> &'a str {
+        self.prefix.as_deref().unwrap_or(&config.prefix)
+    }
+
+    pub fn set_prefix(&mut self, prefix: &str) -> Result<(), String> {
+        if prefix.is_empty() || prefix.chars().any(char::is_whitespace) {
+            return Err(String::from("prefixes can't be empty or contain spaces"));
+        }
+        if prefix.chars().count() > MAX_PREFIX_LENGTH {
+            return Err(format!(
+                "prefixes can be at most {} characters long",
+                MAX_PREFIX_LENGTH
+            ));
+        }
+        if prefix.starts_with("<@") || prefix.starts_with('/') {
+            return Err(String::from(
+                "prefixes can't start with a mention or a slash",
+            ));
+        }
+        self.prefix = Some(prefix.to_string());
+        Ok(())
+    }
+
+    pub fn colour(&self, config: &Config) -> u32 {
+        self.colour.unwrap_or(config.colours.primary)
+    }
+
+    pub fn queue_page_size(&self, config: &Config) -> usize {
+        self.queue_page_size
+            .unwrap_or(config.limits.queue_page_size)
+    }
+
+    pub fn queue_timeout(&self, config: &Config) -> Duration {
+        self.queue_timeout
+            .map(Duration::from_secs)
+            .unwrap_or(config.timeouts.queue)
+    }
+
+    pub fn queue_invoker_only(&self, config: &Config) -> bool {
+        self.queue_invoker_only
+            .unwrap_or(config.limits.queue_invoker_only)
+    }
+
+    pub fn music_channel_allowed(&self, channel_id: ChannelId) -> bool {
+        self.music_channels.is_empty()
+            || self.music_channels.contains(&channel_id.0)
+            || self.request_channel == Some(channel_id.0)
+    }
+
+    pub fn music_voice_channel_allowed(&self, channel_id: ChannelId) -> bool {
+        self.music_voice_channels.is_empty() || self.music_voice_channels.contains(&channel_id.0)
+    }
+
+    /// Mentions of the channels music commands may be used in.
+    pub fn music_channel_mentions(&self) -> String {
+        mention_channels(&self.music_channels)
+    }
+
+    pub fn music_voice_channel_mentions(&self) -> String {
+        mention_channels(&self.music_voice_channels)
+    }
+
+    pub fn delete_misplaced(&self) -> bool {
+        self.delete_misplaced.unwrap_or(false)
+    }
+
+    pub fn dj_role(&self) -> Option<RoleId> {
+        self.dj_role.map(RoleId)
+    }
+
+    pub fn volume(&self) -> u8 {
+        self.volume.unwrap_or(100)
+    }
+
+    pub fn normalize(&self) -> bool {
+        self.normalize.unwrap_or(false)
+    }
+
+    pub fn crossfade(&self) -> Duration {
+        Duration::from_secs(self.crossfade.unwrap_or(0))
+    }
+
+    pub fn skip_segments(&self) -> bool {
+        self.skip_segments.unwrap_or(false)
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "colour" => self.colour = Some(parse_colour(value)?),
+            "queue_page_size" => match value.parse::<usize>() {
+                Ok(size) if (1..=MAX_QUEUE_PAGE_SIZE).contains(&size) => {
+                    self.queue_page_size = Some(size)
+                }
+                _ => {
+                    return Err(format!(
+                        "expected a number between 1 and {}",
+                        MAX_QUEUE_PAGE_SIZE
+                    ))
+                }
+            },
+            "queue_timeout" => match value.parse::<u64>() {
+                Ok(seconds) if (1..=900).contains(&seconds) => self.queue_timeout = Some(seconds),
+                _ => {
+                    return Err(String::from(
+                        "expected a number of seconds between 1 and 900",
+                    ))
+                }
+            },
+            "queue_invoker_only" => self.queue_invoker_only = Some(parse_bool(value)?),
+            "music_channels" => self.music_channels = parse_channels(value)?,
+            "music_voice_channels" => self.music_voice_channels = parse_channels(value)?,
+            "delete_misplaced" => self.delete_misplaced = Some(parse_bool(value)?),
+            "dj_role" => self.dj_role = Some(parse_role(value)?),
+            "normalize" => self.normalize = Some(parse_bool(value)?),
+            "skip_segments" => self.skip_segments = Some(parse_bool(value)?),
+            "crossfade" => match value.trim_end_matches('s').parse::<u64>() {
+                Ok(seconds) if seconds <= MAX_CROSSFADE => self.crossfade = Some(seconds),
+                _ => {
+                    return Err(format!(
+                        "expected a number of seconds between 0 and {}",
+                        MAX_CROSSFADE
+                    ))
+                }
+            },
+            _ => {
+                return Err(format!(
+                    "unknown key, expected one of {}",
+                    SETTING_KEYS.join(", ")
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    pub fn reset(&mut self, key: &str) -> Result<(), String> {
+        match key {
+            "colour" => self.colour = None,
+            "queue_page_size" => self.queue_page_size = None,
+            "queue_timeout" => self.queue_timeout = None,
+            "queue_invoker_only" => self.queue_invoker_only = None,
+            "music_channels" => self.music_channels.clear(),
+            "music_voice_channels" => self.music_voice_channels.clear(),
+            "delete_misplaced" => self.delete_misplaced = None,
+            "dj_role" => self.dj_role = None,
+            "normalize" => self.normalize = None,
+            "crossfade" => self.crossfade = None,
+            "skip_segments" => self.skip_segments = None,
+            _ => {
+                return Err(format!(
+                    "unknown key, expected one of {}",
+                    SETTING_KEYS.join(", ")
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// Lists every key with its effective value, marking the defaults.
+    pub fn describe(&self, config: &Config) -> Vec<(&'static str, String)> {
+        let show = |value: String, overridden: bool| {
+            if overridden {
+                value
+            } else {
+                format!("{} (default)", value)
+            }
+        };
+
+        vec![
+            (
+                "colour",
+                show(
+                    format!("#{:06X}", self.colour(config)),
+                    self.colour.is_some(),
+                ),
+            ),
+            (
+                "queue_page_size",
+                show(
+                    self.queue_page_size(config).to_string(),
+                    self.queue_page_size.is_some(),
+                ),
+            ),
+            (
+                "queue_timeout",
+                show(
+                    format!("{}s", self.queue_timeout(config).as_secs()),
+                    self.queue_timeout.is_some(),
+                ),
+            ),
+            (
+                "queue_invoker_only",
+                show(
+                    self.queue_invoker_only(config).to_string(),
+                    self.queue_invoker_only.is_some(),
+                ),
+            ),
+            (
+                "music_channels",
+                show(
+                    if self.music_channels.is_empty() {
+                        String::from("any")
+                    } else {
+                        self.music_channel_mentions()
+                    },
+                    !self.music_channels.is_empty(),
+                ),
+            ),
+            (
+                "music_voice_channels",
+                show(
+                    if self.music_voice_channels.is_empty() {
+                        String::from("any")
+                    } else {
+                        self.music_voice_channel_mentions()
+                    },
+                    !self.music_voice_channels.is_empty(),
+                ),
+            ),
+            (
+                "delete_misplaced",
+                show(
+                    self.delete_misplaced().to_string(),
+                    self.delete_misplaced.is_some(),
+                ),
+            ),
+            (
+                "dj_role",
+                show(
+                    match self.dj_role() {
+                        Some(role) => role.mention().to_string(),
+                        None => String::from("none, anyone can control playback"),
+                    },
+                    self.dj_role.is_some(),
+                ),
+            ),
+            (
+                "normalize",
+                show(self.normalize().to_string(), self.normalize.is_some()),
+            ),
+            (
+                "crossfade",
+                show(
+                    match self.crossfade().as_secs() {
+                        0 => String::from("off"),
+                        seconds => format!("{}s", seconds),
+                    },
+                    self.crossfade.is_some(),
+                ),
+            ),
+            (
+                "skip_segments",
+                show(
+                    self.skip_segments().to_string(),
+                    self.skip_segments.is_some(),
+                ),
+            ),
+        ]
+    }
+}
+
+/// Guild settings kept in a JSON file under the data directory.
+pub struct GuildStore {
+    path: PathBuf,
+    guilds: HashMap<u64, GuildSettings>,
+}
+
+impl GuildStore {
+    pub fn load(data_dir: &Path) -> std::io::Result<Self> {
+        let path = data_dir.join(GUILD_SETTINGS_FILE);
+        let guilds = if path.exists() {
+            let text = std::fs::read_to_string(&path)?;
+            serde_json::from_str(&text)
+                .map_err(|why| std::io::Error::new(std::io::ErrorKind::InvalidData, why))?
+        } else {
+            HashMap::new()
+        };
+
+        Ok(GuildStore { path, guilds })
+    }
+
+    pub fn get(&self, guild_id: GuildId) -> GuildSettings {
+        self.guilds.get(&guild_id.0).cloned().unwrap_or_default()
+    }
+
+    /// Applies `change` to a guild's settings and writes the store back to
+    /// disk if it succeeded.
+    pub fn update<T>(
+        &mut self,
+        guild_id: GuildId,
+        change: impl FnOnce(&mut GuildSettings) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let mut settings = self.get(guild_id);
+        let result = change(&mut settings)?;
+
+        // Saved from a copy, so a failed save leaves the settings in use as
+        // they are on disk.
+        let mut guilds = self.guilds.clone();
+        guilds.insert(guild_id.0, settings);
+        if let Err(why) = self.save(&guilds) {
+            warn!("Failed to save guild settings: {:?}", why);
+            return Err(String::from("could not save the settings"));
+        }
+        self.guilds = guilds;
+        Ok(result)
+    }
+
+    fn save(&self, guilds: &HashMap<u64, GuildSettings>) -> std::io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let text = serde_json::to_string_pretty(guilds)?;
+        let temp = self.path.with_extension("json.tmp");
+        std::fs::write(&temp, text)?;
+        std::fs::rename(temp, &self.path)
+    }
+}
+
+/// Seconds since the epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or_default()
+}
+
+/// Replaces `path` with `text` through a temporary file, so a crash mid-write
+/// leaves the old contents.
+fn write_atomically(path: &Path, text: &str) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let temp = path.with_extension("tmp");
+    std::fs::write(&temp, text)?;
+    std::fs::rename(temp, path)
+}
+
+/// A file rewritten off the async runtime, for state that is saved while a
+/// lock is held. Writes happen one at a time, and one that is overtaken by a
+/// newer write is dropped.
+pub struct BackgroundFile {
+    path: PathBuf,
+    written: Arc<std::sync::Mutex<u64>>,
+    version: u64,
+}
+
+impl BackgroundFile {
+    pub fn new(path: PathBuf) -> Self {
+        BackgroundFile {
+            path,
+            written: Arc::default(),
+            version: 0,
+        }
+    }
+
+    pub fn write(&mut self, text: String) {
+        self.version += 1;
+        let version = self.version;
+        let path = self.path.clone();
+        let written = self.written.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let mut written = written
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            if *written > version {
+                return;
+            }
+            if let Err(why) = write_atomically(&path, &text) {
+                warn!("Failed to save {}: {:?}", path.display(), why);
+            }
+            *written = version;
+        });
+    }
+}
+
+/// Returns the global config and the settings of `guild_id`, or the defaults
+/// outside of a guild.
+pub async fn guild_settings(
+    ctx: &Context,
+    guild_id: Option<GuildId>,
+) -> (Arc<Config>, GuildSettings) {
+    let data_read = ctx.data.read().await;
+
+    settings_from_data(&data_read, guild_id).await
+}
+
+/// [`guild_settings`] for voice event handlers, which only hold the TypeMap.
+pub async fn settings_from_data(
+    data_read: &TypeMap,
+    guild_id: Option<GuildId>,
+) -> (Arc<Config>, GuildSettings) {
+    let config = data_read
+        .get::<Configuration>()
+        .expect("Expected config in TypeMap.")
+        .clone();
+    let settings = match guild_id {
+        Some(guild_id) => data_read
+            .get::<Guilds>()
+            .expect("Expected guild settings in TypeMap.")
+            .read()
+            .await
+            .get(guild_id),
+        None => GuildSettings::default(),
+    };
+
+    (config, settings)
+}
@@ -0,0 +1,107 @@
+use serenity::framework::standard::{Command, CommandGroup};
+
+/// The furthest a typo can be from a command name and still be suggested.
+pub const MAX_SUGGESTION_DISTANCE: usize = 2;
+
+/// Levenshtein distance between `a` and `b`, counted in characters.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                diagonal
+            } else {
+                1 + diagonal.min(above).min(row[j])
+            };
+            diagonal = above;
+        }
+    }
+
+    row[b.len()]
+}
+
+/// Every name and alias of the top level commands in `groups`.
+pub fn command_names(groups: &[&'static CommandGroup]) -> Vec<&'static str> {
+    groups
+        .iter()
+        .flat_map(|group| group.options.commands.iter())
+        .flat_map(|command| command.options.names.iter().copied())
+        .collect()
+}
+
+/// Finds the top level command called `name` in `groups`, by name or alias.
+pub fn find_command(groups: &[&'static CommandGroup], name: &str) -> Option<&'static Command> {
+    groups
+        .iter()
+        .flat_map(|group| group.options.commands.iter().copied())
+        .find(|command| command.options.names.contains(&name))
+}
+
+/// The candidate closest to `word`, if any is close enough to be a typo of it.
+/// Ties go to the earlier candidate.
+pub fn closest<'a>(word: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let word = word.to_lowercase();
+
+    candidates
+        .iter()
+        .map(|candidate| (edit_distance(&word, candidate), *candidate))
+        .filter(|(distance, candidate)| {
+            *distance <= MAX_SUGGESTION_DISTANCE && *distance < candidate.chars().count()
+        })
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_edit_distance() {
+        let cases = [
+            ("play", "play", 0),
+            ("paly", "play", 2),
+            ("plya", "play", 2),
+            ("pla", "play", 1),
+            ("plays", "play", 1),
+            ("qeue", "queue", 1),
+            ("", "skip", 4),
+            ("naïve", "naive", 1),
+        ];
+
+        for (a, b, expected) in cases {
+            assert_eq!(edit_distance(a, b), expected, "{} -> {}", a, b);
+            assert_eq!(edit_distance(b, a), expected, "{} -> {}", b, a);
+        }
+    }
+
+    #[test]
+    fn suggests_close_commands() {
+        let commands = ["play", "pause", "skip", "stop", "queue", "np"];
+        let cases = [
+            ("play", Some("play")),
+            ("PLAY", Some("play")),
+            ("plya", Some("play")),
+            ("skp", Some("skip")),
+            ("qeueu", Some("queue")),
+            // "stip" is one edit from both, the earlier one wins.
+            ("stip", Some("skip")),
+            ("pxuse", Some("pause")),
+            // Too far from anything.
+            ("lyrics", None),
+            ("xyz", None),
+            // Two edits would turn "n" into anything two letters long.
+            ("n", Some("np")),
+            ("x", None),
+        ];
+
+        for (word, expected) in cases {
+            assert_eq!(closest(word, &commands), expected, "{}", word);
+        }
+    }
+}
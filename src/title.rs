@@ -0,0 +1,432 @@
+use crate::config::TitleWords;
+
+const BRACKETS: [(char, char); 4] = [('(', ')'), ('[', ']'), ('{', '}'), ('【', '】')];
+const SEGMENT_SEPARATORS: [&str; 5] = [" | ", " // ", " - ", " – ", " — "];
+const ARTIST_SEPARATORS: [&str; 3] = [" - ", " – ", " — "];
+
+const DEFAULT_NOISE: [&str; 22] = [
+    "official music video",
+    "official lyric video",
+    "official video",
+    "official audio",
+    "official visualizer",
+    "music video",
+    "lyric video",
+    "lyrics video",
+    "visualizer",
+    "official",
+    "lyrics",
+    "lyric",
+    "video",
+    "audio",
+    "m/v",
+    "mv",
+    "hd",
+    "hq",
+    "4k",
+    "1080p",
+    "720p",
+    "explicit",
+];
+
+const DEFAULT_SUFFIXES: [&str; 5] = ["hd", "hq", "4k", "1080p", "720p"];
+
+const DEFAULT_KEEP: [&str; 20] = [
+    "feat",
+    "ft",
+    "featuring",
+    "with",
+    "prod",
+    "remix",
+    "mix",
+    "edit",
+    "version",
+    "live",
+    "acoustic",
+    "cover",
+    "remaster",
+    "remastered",
+    "instrumental",
+    "extended",
+    "vip",
+    "bootleg",
+    "rework",
+    "flip",
+];
+
+/// Words that may join noise in a group, as in "(Official Video with Lyrics)",
+/// without making it meaningful.
+const FILLER: [&str; 3] = ["with", "and", "in"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NormalizedTitle {
+    pub artist: Option<String>,
+    pub title: String,
+}
+
+/// Rules for stripping upload noise such as "(Official Video)" or "[HD]" from
+/// track titles while keeping meaningful parts like "(feat. X)" or "(Remix)".
+#[derive(Debug, Clone)]
+pub struct TitleRules {
+    noise: Vec<Vec<String>>,
+    suffixes: Vec<String>,
+    keep: Vec<String>,
+}
+
+impl Default for TitleRules {
+    fn default() -> Self {
+        let mut rules = TitleRules::empty();
+        for phrase in DEFAULT_NOISE {
+            rules = rules.noise(phrase);
+        }
+        for suffix in DEFAULT_SUFFIXES {
+            rules = rules.suffix(suffix);
+        }
+        for word in DEFAULT_KEEP {
+            rules = rules.keep(word);
+        }
+        rules
+    }
+}
+
+impl TitleRules {
+    /// The default rules with the words of the `[titles]` config added.
+    pub fn from_config(words: &TitleWords) -> Self {
+        let mut rules = TitleRules::default();
+        for phrase in &words.noise {
+            rules = rules.noise(phrase);
+        }
+        for suffix in &words.suffixes {
+            rules = rules.suffix(suffix);
+        }
+        for word in &words.keep {
+            rules = rules.keep(word);
+        }
+        rules
+    }
+
+    pub fn empty() -> Self {
+        TitleRules {
+            noise: Vec::new(),
+            suffixes: Vec::new(),
+            keep: Vec::new(),
+        }
+    }
+
+    /// Adds a phrase that marks a bracketed group or trailing segment as noise.
+    pub fn noise(mut self, phrase: &str) -> Self {
+        let words = tokenize(phrase);
+        if !words.is_empty() && !self.noise.contains(&words) {
+            self.noise.push(words);
+            self.noise
+                .sort_by_key(|phrase| std::cmp::Reverse(phrase.len()));
+        }
+        self
+    }
+
+    /// Adds a bare word that is removed from the end of a title, e.g. "HD".
+    pub fn suffix(mut self, word: &str) -> Self {
+        let word = word.to_lowercase();
+        if !self.suffixes.contains(&word) {
+            self.suffixes.push(word);
+        }
+        self
+    }
+
+    /// Adds a word that protects a bracketed group from removal.
+    pub fn keep(mut self, word: &str) -> Self {
+        let word = word.to_lowercase();
+        if !self.keep.contains(&word) {
+            self.keep.push(word);
+        }
+        self
+    }
+
+    pub fn normalize(&self, title: &str) -> NormalizedTitle {
+        let cleaned = self.clean(title);
+
+        for separator in ARTIST_SEPARATORS {
+            if let Some((artist, rest)) = cleaned.split_once(separator) {
+                let artist = trim_separators(artist);
+                let rest = trim_separators(rest);
+                if !artist.is_empty() && !rest.is_empty() {
+                    return NormalizedTitle {
+                        artist: Some(artist.to_string()),
+                        title: rest.to_string(),
+                    };
+                }
+            }
+        }
+
+        NormalizedTitle {
+            artist: None,
+            title: cleaned,
+        }
+    }
+
+    /// Removes noise without splitting off the artist.
+    pub fn clean(&self, title: &str) -> String {
+        let mut cleaned = collapse_whitespace(&self.strip_groups(title));
+
+        loop {
+            let before = cleaned.len();
+            cleaned = self.strip_trailing_segment(&cleaned);
+            cleaned = self.strip_trailing_suffix(&cleaned);
+            if cleaned.len() == before {
+                break;
+            }
+        }
+
+        let cleaned = trim_separators(&cleaned).to_string();
+        if cleaned.is_empty() {
+            collapse_whitespace(title)
+        } else {
+            cleaned
+        }
+    }
+
+    fn strip_groups(&self, title: &str) -> String {
+        let chars: Vec<char> = title.chars().collect();
+        let mut output = String::new();
+        let mut i = 0;
+
+        while i < chars.len() {
+            let close = BRACKETS
+                .iter()
+                .find(|(open, _)| *open == chars[i])
+                .map(|(_, close)| *close);
+
+            if let Some(close) = close {
+                if let Some(end) = find_matching(&chars, i, chars[i], close) {
+                    let inner: String = chars[i + 1..end].iter().collect();
+                    if self.is_pure_noise(&inner) {
+                        output.push(' ');
+                    } else {
+                        output.push(chars[i]);
+                        output.push_str(&self.strip_groups(&inner));
+                        output.push(close);
+                    }
+                    i = end + 1;
+                    continue;
+                }
+            }
+
+            output.push(chars[i]);
+            i += 1;
+        }
+
+        output
+    }
+
+    /// Whether `text` is only noise phrases, with filler words or years
+    /// between them, and no keep words.
+    fn is_pure_noise(&self, text: &str) -> bool {
+        let words = tokenize(text);
+        if words
+            .iter()
+            .any(|word| self.keep.contains(word) && !is_filler(word))
+        {
+            return false;
+        }
+        let mut found = false;
+
+        let mut i = 0;
+        while i < words.len() {
+            if let Some(len) = self.noise_at(&words, i) {
+                found = true;
+                i += len;
+            } else if is_filler(&words[i]) {
+                i += 1;
+            } else {
+                return false;
+            }
+        }
+        found
+    }
+
+    fn noise_at(&self, words: &[String], start: usize) -> Option<usize> {
+        self.noise
+            .iter()
+            .find(|phrase| words[start..].starts_with(phrase))
+            .map(|phrase| phrase.len())
+    }
+
+    fn strip_trailing_segment(&self, title: &str) -> String {
+        let split = SEGMENT_SEPARATORS
+            .iter()
+            .filter_map(|separator| {
+                title
+                    .rfind(separator)
+                    .map(|index| (index, index + separator.len()))
+            })
+            .max_by_key(|(index, _)| *index);
+
+        match split {
+            // A lone word after the artist, as in "Artist - Audio", is taken
+            // as the title rather than noise.
+            Some((index, rest))
+                if is_artist_split(title, index) && tokenize(&title[rest..]).len() == 1 =>
+            {
+                title.to_string()
+            }
+            Some((index, rest)) if self.is_pure_noise(&title[rest..]) => {
+                title[..index].trim_end().to_string()
+            }
+            _ => title.to_string(),
+        }
+    }
+
+    fn strip_trailing_suffix(&self, title: &str) -> String {
+        let trimmed = title.trim_end();
+        match trimmed.rsplit_once(char::is_whitespace) {
+            // Not when the suffix is a segment of its own, as in "Artist - HD".
+            Some((rest, last))
+                if self.suffixes.contains(&normalize_word(last))
+                    && trim_separators(rest).len() == rest.trim().len() =>
+            {
+                rest.trim_end().to_string()
+            }
+            _ => trimmed.to_string(),
+        }
+    }
+}
+
+fn is_filler(word: &str) -> bool {
+    FILLER.contains(&word) || (word.len() == 4 && word.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Whether the separator at `index` is the only one between an artist and a
+/// title, so what follows it is the whole title.
+fn is_artist_split(title: &str, index: usize) -> bool {
+    let before = &title[..index];
+    ARTIST_SEPARATORS
+        .iter()
+        .any(|separator| title[index..].starts_with(separator))
+        && !SEGMENT_SEPARATORS
+            .iter()
+            .any(|separator| before.contains(separator))
+}
+
+fn find_matching(chars: &[char], start: usize, open: char, close: char) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in chars.iter().enumerate().skip(start) {
+        if *c == open {
+            depth += 1;
+        } else if *c == close {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+fn normalize_word(word: &str) -> String {
+    word.trim_matches(|c: char| !c.is_alphanumeric())
+        .to_lowercase()
+}
+
+fn tokenize(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .map(normalize_word)
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+fn trim_separators(text: &str) -> &str {
+    text.trim_matches(|c: char| c.is_whitespace() || matches!(c, '-' | '|' | '/' | '–' | '—' | ':'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cleans_titles() {
+        let rules = TitleRules::default();
+        let cases = [
+            (
+                "Never Gonna Give You Up (Official Video)",
+                "Never Gonna Give You Up",
+            ),
+            ("Song [Official Music Video] [HD]", "Song"),
+            ("Song (Official Video with lyrics)", "Song"),
+            ("Song (Official HD Video 2009)", "Song"),
+            ("Song (Lyrics) | Official Audio", "Song"),
+            ("Song - Official Video", "Song"),
+            ("Artist - Song - Lyric Video", "Artist - Song"),
+            ("Song HD", "Song"),
+            ("Song 1080p", "Song"),
+            (
+                "Song (feat. Someone) [Official Video]",
+                "Song (feat. Someone)",
+            ),
+            ("Song (with Someone)", "Song (with Someone)"),
+            ("Song (Extended Mix)", "Song (Extended Mix)"),
+            ("Song (Live Video)", "Song (Live Video)"),
+            ("Song (Remix) [Lyrics]", "Song (Remix)"),
+            (
+                "Video Killed the Radio Star (Video Killed the Radio Star)",
+                "Video Killed the Radio Star (Video Killed the Radio Star)",
+            ),
+            ("Artist - Audio", "Artist - Audio"),
+            ("Artist - HD", "Artist - HD"),
+            ("Official Video", "Official Video"),
+            ("  Spaced    out  ", "Spaced out"),
+            ("Song (unclosed", "Song (unclosed"),
+        ];
+
+        for (title, cleaned) in cases {
+            assert_eq!(rules.clean(title), cleaned, "{}", title);
+        }
+    }
+
+    #[test]
+    fn splits_artists() {
+        let rules = TitleRules::default();
+        let cases = [
+            (
+                "Rick Astley - Never Gonna Give You Up (Official Video)",
+                Some("Rick Astley"),
+                "Never Gonna Give You Up",
+            ),
+            ("Artist – Song [HD]", Some("Artist"), "Song"),
+            ("Artist - Audio", Some("Artist"), "Audio"),
+            ("Just a Song", None, "Just a Song"),
+            ("- Song -", None, "Song"),
+        ];
+
+        for (title, artist, song) in cases {
+            let normalized = rules.normalize(title);
+            assert_eq!(normalized.artist.as_deref(), artist, "{}", title);
+            assert_eq!(normalized.title, song, "{}", title);
+        }
+    }
+
+    #[test]
+    fn adds_configured_words() {
+        let rules = TitleRules::from_config(&TitleWords {
+            noise: vec![String::from("full album")],
+            suffixes: vec![String::from("8k")],
+            keep: vec![String::from("audio")],
+        });
+
+        assert_eq!(rules.clean("Album (Full Album)"), "Album");
+        assert_eq!(rules.clean("Song 8K"), "Song");
+        assert_eq!(
+            rules.clean("Song (Official Audio)"),
+            "Song (Official Audio)"
+        );
+
+        let empty = TitleRules::empty();
+        assert_eq!(
+            empty.clean("Song (Official Video)"),
+            "Song (Official Video)"
+        );
+    }
+}
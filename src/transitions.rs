@@ -0,0 +1,104 @@
+use crate::filters::AudioFilters;
+use crate::storage::settings_from_data;
+use serenity::async_trait;
+use serenity::model::id::GuildId;
+use serenity::prelude::TypeMap;
+use songbird::tracks::{LoopState, PlayMode, TrackHandle, TrackQueue, TrackState};
+use songbird::{Event, EventContext, EventHandler as VoiceEventHandler};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+/// How often a playing track checks whether the next one should load or
+/// fade in.
+pub const TRANSITION_TICK: Duration = Duration::from_millis(250);
+
+/// How long before a track ends the next one starts loading, so it is ready
+/// the moment it is needed.
+const PRELOAD_AHEAD: Duration = Duration::from_secs(20);
+
+/// The longest crossfade a server can set, in seconds.
+pub const MAX_CROSSFADE: u64 = 12;
+
+/// Watches a queued track while it plays: loads the track after it ahead of
+/// time, and with a crossfade set starts it early and fades between the two.
+/// The fade is driven from the track that is ending, as the builtin queue only
+/// moves on once it has.
+pub struct Transitions {
+    pub queue: TrackQueue,
+    pub data: Arc<RwLock<TypeMap>>,
+    pub filters: Arc<RwLock<AudioFilters>>,
+    pub guild_id: GuildId,
+}
+
+impl Transitions {
+    /// How much of `track` is left to play, if its length is known.
+    async fn remaining(&self, track: &TrackHandle, state: &TrackState) -> Option<Duration> {
+        let length = track.metadata().duration?;
+        // Filters that change speed change how long the track plays for.
+        let speed = self.filters.read().await.speed_factor();
+
+        Some(length.div_f64(speed).saturating_sub(state.position))
+    }
+}
+
+#[async_trait]
+impl VoiceEventHandler for Transitions {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let (state, track) = match ctx {
+            EventContext::Track(tracks) => *tracks.first()?,
+            _ => return None,
+        };
+
+        let queued = self.queue.current_queue();
+        match queued.first() {
+            Some(head) if head.uuid() == track.uuid() => {}
+            // Still fading in, or no longer queued.
+            _ => return None,
+        }
+
+        let (_, settings) = {
+            let data_read = self.data.read().await;
+            settings_from_data(&data_read, Some(self.guild_id)).await
+        };
+        let volume = f32::from(settings.volume()) / 100.0;
+        let crossfade = settings.crossfade();
+
+        let next = queued.get(1);
+        let remaining = match self.remaining(track, state).await {
+            Some(remaining) if state.loops == LoopState::Finite(0) => remaining,
+            _ => {
+                restore_volume(track, state, volume);
+                return None;
+            }
+        };
+
+        if let Some(next) = next {
+            if remaining <= PRELOAD_AHEAD + crossfade {
+                let _ = next.make_playable();
+            }
+        }
+
+        match next {
+            Some(next) if !crossfade.is_zero() && remaining < crossfade => {
+                let faded_in = 1.0 - remaining.as_secs_f32() / crossfade.as_secs_f32();
+                let _ = track.set_volume(volume * (1.0 - faded_in));
+                let _ = next.set_volume(volume * faded_in);
+                if state.playing == PlayMode::Play {
+                    let _ = next.play();
+                }
+            }
+            _ => restore_volume(track, state, volume),
+        }
+
+        None
+    }
+}
+
+/// Brings a track that has finished fading in, or was skipped to halfway
+/// through, up to the server's volume.
+fn restore_volume(track: &TrackHandle, state: &TrackState, volume: f32) {
+    if (state.volume - volume).abs() > f32::EPSILON {
+        let _ = track.set_volume(volume);
+    }
+}
@@ -0,0 +1,116 @@
+use std::time::Duration;
+
+/// Formats `duration` as `m:ss`, or `h:mm:ss` from an hour on.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds >= 3600 {
+        format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            (seconds / 60) % 60,
+            seconds % 60
+        )
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+/// The id of a Youtube video url, if it is one.
+pub fn video_id(url: &str) -> Option<&str> {
+    let url = url.trim();
+    let rest = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+        .unwrap_or(url);
+    let (host, path) = rest.split_once('/')?;
+    let host = ["www.", "m.", "music."]
+        .iter()
+        .find_map(|sub| host.strip_prefix(sub))
+        .unwrap_or(host);
+    let path = path.split('#').next()?;
+    let (path, query) = path.split_once('?').unwrap_or((path, ""));
+
+    let id = match (host, path.split_once('/')) {
+        ("youtu.be", None) => path,
+        ("youtube.com", None) if path == "watch" => query
+            .split('&')
+            .find_map(|param| param.strip_prefix("v="))?,
+        ("youtube.com", Some(("shorts" | "embed" | "live", id))) => id.trim_end_matches('/'),
+        _ => return None,
+    };
+
+    let valid = id.len() == 11
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Some(id)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_durations() {
+        let cases = [
+            (0, "0:00"),
+            (9, "0:09"),
+            (75, "1:15"),
+            (3599, "59:59"),
+            (3600, "1:00:00"),
+            (3723, "1:02:03"),
+            (36_000, "10:00:00"),
+        ];
+
+        for (seconds, expected) in cases {
+            assert_eq!(format_duration(Duration::from_secs(seconds)), expected);
+        }
+    }
+
+    #[test]
+    fn finds_video_ids() {
+        let cases = [
+            (
+                "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+                Some("dQw4w9WgXcQ"),
+            ),
+            (
+                "https://youtube.com/watch?list=PL0123&v=dQw4w9WgXcQ&t=42",
+                Some("dQw4w9WgXcQ"),
+            ),
+            (
+                "https://music.youtube.com/watch?v=dQw4w9WgXcQ#top",
+                Some("dQw4w9WgXcQ"),
+            ),
+            (
+                "http://m.youtube.com/watch?v=dQw4w9WgXcQ",
+                Some("dQw4w9WgXcQ"),
+            ),
+            ("youtube.com/watch?v=dQw4w9WgXcQ", Some("dQw4w9WgXcQ")),
+            ("https://youtu.be/dQw4w9WgXcQ?t=10", Some("dQw4w9WgXcQ")),
+            (
+                "https://www.youtube.com/shorts/a-B_c1234XY",
+                Some("a-B_c1234XY"),
+            ),
+            (
+                "https://www.youtube.com/embed/dQw4w9WgXcQ",
+                Some("dQw4w9WgXcQ"),
+            ),
+            ("https://www.youtube.com/watch?xv=dQw4w9WgXcQ", None),
+            ("https://www.youtube.com/watch?v=dQw4w9WgXc", None),
+            ("https://www.youtube.com/watch?v=dQw4w9WgXc!", None),
+            ("https://www.youtube.com/playlist?list=PL0123", None),
+            ("https://example.com/watch?v=dQw4w9WgXcQ", None),
+            ("https://notyoutu.be/dQw4w9WgXcQ", None),
+            ("never gonna give you up", None),
+        ];
+
+        for (url, expected) in cases {
+            assert_eq!(video_id(url), expected, "{}", url);
+        }
+    }
+}